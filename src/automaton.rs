@@ -0,0 +1,402 @@
+use crate::point::Point;
+use std::collections::HashSet;
+use std::ops::{Index, IndexMut};
+
+/// Which cells around a cell count as its neighbors.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Neighborhood {
+    /// The four orthogonal neighbors.
+    VonNeumann,
+    /// The orthogonal and diagonal neighbors.
+    #[default]
+    Moore,
+}
+
+impl Neighborhood {
+    fn offsets(self) -> &'static [Point] {
+        const VON_NEUMANN: [Point; 4] = [
+            Point { x: 0, y: -1 },
+            Point { x: -1, y: 0 },
+            Point { x: 1, y: 0 },
+            Point { x: 0, y: 1 },
+        ];
+        const MOORE: [Point; 8] = [
+            Point { x: -1, y: -1 },
+            Point { x: 0, y: -1 },
+            Point { x: 1, y: -1 },
+            Point { x: -1, y: 0 },
+            Point { x: 1, y: 0 },
+            Point { x: -1, y: 1 },
+            Point { x: 0, y: 1 },
+            Point { x: 1, y: 1 },
+        ];
+        match self {
+            Neighborhood::VonNeumann => &VON_NEUMANN,
+            Neighborhood::Moore => &MOORE,
+        }
+    }
+}
+
+/// What lies past the edge of a [`Grid`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edges {
+    /// Nothing: cells on the edge have fewer neighbors.
+    #[default]
+    Open,
+    /// The grid wraps around, so opposite edges are adjacent.
+    Wrapping,
+}
+
+/// A rectangular grid of cells, stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid<T> {
+    cells: Vec<T>,
+    width: i64,
+    height: i64,
+    edges: Edges,
+}
+
+impl<T> Grid<T> {
+    /// Builds a grid from its rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rows are not all the same length.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Self, String> {
+        let width = rows.first().map_or(0, Vec::len);
+        if let Some(row) = rows.iter().find(|row| row.len() != width) {
+            return Err(format!(
+                "rows must all be {width} cells wide, found one with {}",
+                row.len()
+            ));
+        }
+        let height = rows.len();
+        Ok(Self {
+            cells: rows.into_iter().flatten().collect(),
+            width: i64::try_from(width).map_err(|_| "grid too wide")?,
+            height: i64::try_from(height).map_err(|_| "grid too tall")?,
+            edges: Edges::default(),
+        })
+    }
+
+    #[must_use]
+    pub fn with_edges(self, edges: Edges) -> Self {
+        Self { edges, ..self }
+    }
+
+    #[must_use]
+    pub fn width(&self) -> i64 {
+        self.width
+    }
+
+    #[must_use]
+    pub fn height(&self) -> i64 {
+        self.height
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The cells, row by row.
+    #[must_use]
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    /// Every point on the grid, row by row.
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let (width, height) = (self.width, self.height);
+        (0..height).flat_map(move |y| (0..width).map(move |x| Point { x, y }))
+    }
+
+    #[must_use]
+    pub fn get(&self, point: Point) -> Option<&T> {
+        self.index_for(point).map(|i| &self.cells[i])
+    }
+
+    pub fn get_mut(&mut self, point: Point) -> Option<&mut T> {
+        self.index_for(point).map(|i| &mut self.cells[i])
+    }
+
+    /// The distinct points around `point`, never including `point` itself.
+    /// On a small wrapping grid a neighbor reachable from more than one side
+    /// is still only listed once.
+    #[must_use]
+    pub fn neighbors(&self, point: Point, neighborhood: Neighborhood) -> Vec<Point> {
+        let mut result = vec![];
+        for &offset in neighborhood.offsets() {
+            let neighbor = match self.edges {
+                Edges::Open => point + offset,
+                Edges::Wrapping => Point {
+                    x: (point.x + offset.x).rem_euclid(self.width),
+                    y: (point.y + offset.y).rem_euclid(self.height),
+                },
+            };
+            let fresh = neighbor != point && !result.contains(&neighbor);
+            if fresh && self.index_for(neighbor).is_some() {
+                result.push(neighbor);
+            }
+        }
+        result
+    }
+
+    fn index_for(&self, Point { x, y }: Point) -> Option<usize> {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            usize::try_from(y * self.width + x).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Index<Point> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, point: Point) -> &Self::Output {
+        self.get(point).expect("out of bounds")
+    }
+}
+
+impl<T> IndexMut<Point> for Grid<T> {
+    fn index_mut(&mut self, point: Point) -> &mut Self::Output {
+        self.get_mut(point).expect("out of bounds")
+    }
+}
+
+/// How the cells of an [`Automaton`] change from one step to the next.
+///
+/// A step first replaces every cell with [`Rule::next`], computed from the
+/// previous generation. Cells that then [`Rule::fires`] start a cascade:
+/// each of their neighbors that hasn't fired yet this step
+/// [`Rule::receive`]s from them and may fire in turn, until no new cells
+/// fire. Finally every cell that fired is [`Rule::settle`]d. A rule that
+/// never fires is a plain synchronous automaton.
+pub trait Rule<T> {
+    fn neighborhood(&self) -> Neighborhood {
+        Neighborhood::default()
+    }
+
+    /// The next value of `cell`, given the current values of its neighbors.
+    fn next(&self, cell: &T, neighbors: &[&T]) -> T;
+
+    fn fires(&self, _cell: &T) -> bool {
+        false
+    }
+
+    /// Applied to a neighbor of a cell that just fired.
+    fn receive(&self, _cell: &mut T) {}
+
+    /// Applied to every cell that fired, once the cascade has died out.
+    fn settle(&self, _cell: &mut T) {}
+}
+
+/// A [`Grid`] evolving under a [`Rule`].
+#[derive(Debug, Clone)]
+pub struct Automaton<T, R> {
+    grid: Grid<T>,
+    rule: R,
+    steps: usize,
+}
+
+impl<T, R: Rule<T>> Automaton<T, R> {
+    pub fn new(grid: Grid<T>, rule: R) -> Self {
+        Self {
+            grid,
+            rule,
+            steps: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn grid(&self) -> &Grid<T> {
+        &self.grid
+    }
+
+    #[must_use]
+    pub fn into_grid(self) -> Grid<T> {
+        self.grid
+    }
+
+    /// Steps taken so far.
+    #[must_use]
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Advances one step, returning the cells that fired grouped by cascade
+    /// wave.
+    pub fn step(&mut self) -> Vec<Vec<Point>> {
+        let neighborhood = self.rule.neighborhood();
+        let next: Vec<T> = self
+            .grid
+            .points()
+            .map(|point| {
+                let neighbors: Vec<&T> = self
+                    .grid
+                    .neighbors(point, neighborhood)
+                    .into_iter()
+                    .map(|neighbor| &self.grid[neighbor])
+                    .collect();
+                self.rule.next(&self.grid[point], &neighbors)
+            })
+            .collect();
+        self.grid.cells = next;
+
+        let mut fired: HashSet<Point> = HashSet::new();
+        let mut waves = vec![];
+        let mut wave: Vec<Point> = self
+            .grid
+            .points()
+            .filter(|&point| self.rule.fires(&self.grid[point]))
+            .collect();
+        while !wave.is_empty() {
+            fired.extend(&wave);
+            let mut next_wave = vec![];
+            for &point in &wave {
+                for neighbor in self.grid.neighbors(point, neighborhood) {
+                    if fired.contains(&neighbor) || next_wave.contains(&neighbor) {
+                        continue;
+                    }
+                    let cell = &mut self.grid[neighbor];
+                    self.rule.receive(cell);
+                    if self.rule.fires(cell) {
+                        next_wave.push(neighbor);
+                    }
+                }
+            }
+            waves.push(wave);
+            wave = next_wave;
+        }
+        for point in fired {
+            self.rule.settle(&mut self.grid[point]);
+        }
+
+        self.steps += 1;
+        waves
+    }
+
+    /// Steps until `done` accepts the automaton and the waves of the step
+    /// that got it there, returning the step counter at that point.
+    ///
+    /// This never returns if `done` is never satisfied; see
+    /// [`crate::cycle::search`] for a bounded search.
+    pub fn run_until<P>(&mut self, mut done: P) -> usize
+    where
+        P: FnMut(&Self, &[Vec<Point>]) -> bool,
+    {
+        loop {
+            let waves = self.step();
+            if done(self, &waves) {
+                return self.steps;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod automaton_tests {
+    use super::*;
+
+    /// Conway's Game of Life.
+    struct Life;
+
+    impl Rule<bool> for Life {
+        fn next(&self, &alive: &bool, neighbors: &[&bool]) -> bool {
+            let living = neighbors.iter().filter(|&&&n| n).count();
+            living == 3 || (alive && living == 2)
+        }
+    }
+
+    fn life(rows: &[&str]) -> Grid<bool> {
+        Grid::from_rows(
+            rows.iter()
+                .map(|row| row.chars().map(|c| c == '#').collect())
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    /// Counts up, firing at 3, passing one unit to each neighbor.
+    struct Sandpile;
+
+    impl Rule<u8> for Sandpile {
+        fn neighborhood(&self) -> Neighborhood {
+            Neighborhood::VonNeumann
+        }
+
+        fn next(&self, &cell: &u8, _: &[&u8]) -> u8 {
+            cell + 1
+        }
+
+        fn fires(&self, &cell: &u8) -> bool {
+            cell >= 3
+        }
+
+        fn receive(&self, cell: &mut u8) {
+            *cell += 1;
+        }
+
+        fn settle(&self, cell: &mut u8) {
+            *cell = 0;
+        }
+    }
+
+    #[test]
+    fn test_synchronous_blinker() {
+        let blinker = life(&[".....", "..#..", "..#..", "..#..", "....."]);
+        let mut automaton = Automaton::new(blinker.clone(), Life);
+        assert!(automaton.step().is_empty());
+        assert_eq!(
+            automaton.grid(),
+            &life(&[".....", ".....", ".###.", ".....", "....."])
+        );
+        assert_eq!(
+            2,
+            automaton.run_until(|automaton, _| automaton.grid() == &blinker)
+        );
+        assert_eq!(2, automaton.steps());
+    }
+
+    #[test]
+    fn test_wrapping_glider() {
+        let glider = life(&[".#....", "..#...", "###...", "......", "......", "......"])
+            .with_edges(Edges::Wrapping);
+        let mut automaton = Automaton::new(glider.clone(), Life);
+        let steps = automaton.run_until(|automaton, _| automaton.grid() == &glider);
+        assert_eq!(24, steps);
+    }
+
+    #[test]
+    fn test_cascade() {
+        let grid = Grid::from_rows(vec![vec![2, 1, 0, 0]]).unwrap();
+        let mut automaton = Automaton::new(grid, Sandpile);
+        let waves = automaton.step();
+        assert_eq!(
+            waves,
+            vec![vec![Point { x: 0, y: 0 }], vec![Point { x: 1, y: 0 }]]
+        );
+        assert_eq!(automaton.grid().cells(), &[0, 0, 2, 1]);
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let grid = Grid::from_rows(vec![vec![0; 3]; 2]).unwrap();
+        let corner = Point { x: 0, y: 0 };
+        assert_eq!(grid.neighbors(corner, Neighborhood::Moore).len(), 3);
+        assert_eq!(grid.neighbors(corner, Neighborhood::VonNeumann).len(), 2);
+        let wrapping = grid.with_edges(Edges::Wrapping);
+        assert_eq!(wrapping.neighbors(corner, Neighborhood::Moore).len(), 5);
+        assert_eq!(
+            wrapping.neighbors(corner, Neighborhood::VonNeumann).len(),
+            3
+        );
+        assert!(Grid::from_rows(vec![vec![1, 2], vec![3]]).is_err());
+    }
+}
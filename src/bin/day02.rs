@@ -1,52 +1,192 @@
 #![warn(clippy::all, clippy::pedantic)]
-use aoc2021::{input_lines, parse_lines};
+use aoc2021::input_lines;
+use std::env;
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Command {
     Forward(i64),
     Down(i64),
     Up(i64),
+    Left(i64),
+    Right(i64),
 }
 
 impl FromStr for Command {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split(' ').collect();
-        let direction = parts[0];
-        let units = parts[1].parse::<i64>().map_err(|e| e.to_string())?;
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let [direction, units] = parts[..] else {
+            return Err(format!("expected \"<verb> <units>\", got {s:?}"));
+        };
+        let units = units
+            .parse::<i64>()
+            .map_err(|e| format!("bad units in {s:?}: {e}"))?;
 
         match direction {
             "forward" => Ok(Command::Forward(units)),
             "down" => Ok(Command::Down(units)),
             "up" => Ok(Command::Up(units)),
-            _ => Err(String::from("wtf")),
+            "left" => Ok(Command::Left(units)),
+            "right" => Ok(Command::Right(units)),
+            _ => Err(format!("unknown verb {direction:?} in {s:?}")),
         }
     }
 }
 
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Forward(units) => write!(f, "forward {units}"),
+            Command::Down(units) => write!(f, "down {units}"),
+            Command::Up(units) => write!(f, "up {units}"),
+            Command::Left(units) => write!(f, "left {units}"),
+            Command::Right(units) => write!(f, "right {units}"),
+        }
+    }
+}
+
+/// Parses a command script, skipping blank lines. Errors name the 1-based
+/// line they were found on.
+fn parse_commands<I>(lines: I) -> Result<Vec<Command>, String>
+where
+    I: Iterator<Item = String>,
+{
+    lines
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            line.trim()
+                .parse()
+                .map_err(|e| format!("line {}: {e}", i + 1))
+        })
+        .collect()
+}
+
 fn main() {
-    let input: Vec<Command> = parse_lines(input_lines()).collect();
-    let result = part1(&input);
-    println!("Part 1: {}", result);
-    let result = part2(&input);
-    println!("Part 2: {}", result);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let input = match parse_commands(input_lines()) {
+        Ok(commands) => commands,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    match args[..] {
+        ["trace", model] => {
+            let csv = match model {
+                "direct" => trace::<Direct>(&input),
+                "aim" => trace::<Heading>(&input),
+                "3d" => trace::<Heading3d>(&input),
+                _ => panic!("unknown model {model}, expected direct, aim or 3d"),
+            };
+            print!("{csv}");
+        }
+        ["3d"] => {
+            let position = navigate::<Heading3d>(&input).position();
+            println!(
+                "horizontal {}, depth {}, lateral {}",
+                position.horizontal, position.depth, position.lateral
+            );
+        }
+        _ => {
+            let result = part1(&input);
+            println!("Part 1: {result}");
+            let result = part2(&input);
+            println!("Part 2: {result}");
+        }
+    }
 }
 
 fn part1(commands: &[Command]) -> i64 {
-    let (horizontal_position, depth) =
-        commands
-            .iter()
-            .fold((0, 0), |(hp, d), command| match command {
-                Command::Forward(units) => (hp + units, d),
-                Command::Down(units) => (hp, d + units),
-                Command::Up(units) => (hp, d - units),
-            });
-    horizontal_position * depth
+    let position = navigate::<Direct>(commands).position();
+    position.horizontal * position.depth
+}
+
+fn part2(commands: &[Command]) -> i64 {
+    let position = navigate::<Heading>(commands).position();
+    position.horizontal * position.depth
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    horizontal: i64,
+    depth: i64,
+    lateral: i64,
 }
 
+/// One way of reading the submarine's commands.
+trait Navigator: Default {
+    /// Where the submarine is after following `command` from here.
+    #[must_use]
+    fn follow(&self, command: &Command) -> Self;
+
+    fn position(&self) -> Position;
+}
+
+fn navigate<N: Navigator>(commands: &[Command]) -> N {
+    commands
+        .iter()
+        .fold(N::default(), |navigator, command| navigator.follow(command))
+}
+
+/// The position after every command as CSV, starting from the origin.
+fn trace<N: Navigator>(commands: &[Command]) -> String {
+    let mut navigator = N::default();
+    let mut csv = String::from("step,command,horizontal,depth,lateral\n");
+    csv.push_str(&csv_row(0, "", navigator.position()));
+    for (i, command) in commands.iter().enumerate() {
+        navigator = navigator.follow(command);
+        csv.push_str(&csv_row(i + 1, &command.to_string(), navigator.position()));
+    }
+    csv
+}
+
+fn csv_row(step: usize, command: &str, position: Position) -> String {
+    format!(
+        "{step},{command},{},{},{}\n",
+        position.horizontal, position.depth, position.lateral
+    )
+}
+
+/// `up` and `down` change the depth directly. There is no lateral axis, so
+/// `left` and `right` do nothing.
 #[derive(Default)]
+struct Direct {
+    depth: i64,
+    horizontal_position: i64,
+}
+
+impl Navigator for Direct {
+    fn follow(&self, command: &Command) -> Self {
+        let (hp, d) = (self.horizontal_position, self.depth);
+        let (horizontal_position, depth) = match *command {
+            Command::Forward(units) => (hp + units, d),
+            Command::Down(units) => (hp, d + units),
+            Command::Up(units) => (hp, d - units),
+            Command::Left(_) | Command::Right(_) => (hp, d),
+        };
+        Self {
+            depth,
+            horizontal_position,
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            horizontal: self.horizontal_position,
+            depth: self.depth,
+            lateral: 0,
+        }
+    }
+}
+
+/// `up` and `down` change the aim, and `forward` dives along it. There is
+/// no lateral axis, so `left` and `right` do nothing.
+#[derive(Default, Clone, Copy)]
 struct Heading {
     depth: i64,
     horizontal_position: i64,
@@ -79,17 +219,55 @@ impl Heading {
     }
 }
 
-fn part2(commands: &[Command]) -> i64 {
-    let heading =
-        commands
-            .iter()
-            .cloned()
-            .fold(Heading::default(), |heading, command| match command {
-                Command::Forward(units) => heading.forward(units),
-                Command::Down(units) => heading.down(units),
-                Command::Up(units) => heading.up(units),
-            });
-    heading.horizontal_position * heading.depth
+impl Navigator for Heading {
+    fn follow(&self, command: &Command) -> Self {
+        match *command {
+            Command::Forward(units) => self.forward(units),
+            Command::Down(units) => self.down(units),
+            Command::Up(units) => self.up(units),
+            Command::Left(_) | Command::Right(_) => *self,
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            horizontal: self.horizontal_position,
+            depth: self.depth,
+            lateral: 0,
+        }
+    }
+}
+
+/// [`Heading`] with a second aim: `left` and `right` turn the submarine, and
+/// `forward` also drifts it sideways by that aim.
+#[derive(Default)]
+struct Heading3d {
+    heading: Heading,
+    lateral: i64,
+    yaw: i64,
+}
+
+impl Navigator for Heading3d {
+    fn follow(&self, command: &Command) -> Self {
+        let (lateral, yaw) = match *command {
+            Command::Forward(units) => (self.lateral + self.yaw * units, self.yaw),
+            Command::Right(units) => (self.lateral, self.yaw + units),
+            Command::Left(units) => (self.lateral, self.yaw - units),
+            Command::Down(_) | Command::Up(_) => (self.lateral, self.yaw),
+        };
+        Self {
+            heading: self.heading.follow(command),
+            lateral,
+            yaw,
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            lateral: self.lateral,
+            ..self.heading.position()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -116,4 +294,56 @@ mod day2_tests {
         let result = part2(&EXAMPLE_INPUT);
         assert_eq!(result, 900);
     }
+
+    #[test]
+    fn test_3d_model() {
+        let mut commands = EXAMPLE_INPUT.to_vec();
+        commands.insert(2, Command::Right(2));
+        commands.push(Command::Left(5));
+        commands.push(Command::Forward(1));
+        let position = navigate::<Heading3d>(&commands).position();
+        assert_eq!(
+            position,
+            Position {
+                horizontal: 16,
+                depth: 70,
+                lateral: 20 - 3,
+            }
+        );
+        assert_eq!(navigate::<Heading>(&commands).position().lateral, 0);
+    }
+
+    #[test]
+    fn test_trace() {
+        let csv = trace::<Heading>(&EXAMPLE_INPUT[..3]);
+        assert_eq!(
+            csv,
+            "step,command,horizontal,depth,lateral\n\
+             0,,0,0,0\n\
+             1,forward 5,5,0,0\n\
+             2,down 5,5,0,0\n\
+             3,forward 8,13,40,0\n"
+        );
+        let csv = trace::<Direct>(&EXAMPLE_INPUT[..2]);
+        assert!(csv.ends_with("2,down 5,5,5,0\n"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Ok(Command::Left(3)), "left 3".parse());
+        assert_eq!(
+            Err("unknown verb \"backward\" in \"backward 2\"".to_string()),
+            "backward 2".parse::<Command>()
+        );
+        let lines = ["forward 5", "", "down x"].iter().map(ToString::to_string);
+        assert_eq!(
+            Err("line 3: bad units in \"down x\": invalid digit found in string".to_string()),
+            parse_commands(lines)
+        );
+        let lines = ["up"].iter().map(ToString::to_string);
+        assert_eq!(
+            Err("line 1: expected \"<verb> <units>\", got \"up\"".to_string()),
+            parse_commands(lines)
+        );
+    }
 }
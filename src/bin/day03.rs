@@ -1,104 +1,451 @@
 #![warn(clippy::all, clippy::pedantic)]
 use aoc2021::input_lines;
+use std::cmp::Ordering;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
 
 fn main() {
-    let binary_numbers: Vec<String> = input_lines().map(|line| line.trim().to_string()).collect();
-    let result = part1(&binary_numbers);
-    println!("Part 1: {}", result);
-    let result = part2(&binary_numbers);
-    println!("Part 2: {}", result);
+    let report = DiagnosticReport::from_lines(input_lines()).expect("invalid report");
+    let result = part1(&report);
+    println!("Part 1: {result}");
+    let result = part2(&report);
+    println!("Part 2: {result}");
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>()[..] {
+        ["prefix", prefix] => {
+            let count = report.trie.count_with_prefix(prefix);
+            println!("Numbers starting with {prefix}: {count}");
+        }
+        ["common", len] => {
+            let len = len.parse().expect("prefix length must be a number");
+            match report.trie.most_common_prefix(len) {
+                Some((prefix, count)) => println!("Most common prefix: {prefix} ({count})"),
+                None => println!("No numbers have {len} bits"),
+            }
+        }
+        ["select", criteria] => {
+            let criteria: BitCriteria = criteria.parse().expect("invalid criteria");
+            println!("Selected: {}", report.select_by_criteria(criteria));
+        }
+        ["filter", criteria] => {
+            let criteria: BitCriteria = criteria.parse().expect("invalid criteria");
+            println!("Filtered: {}", report.filter_by_criteria(criteria));
+        }
+        _ => {}
+    }
 }
 
-fn part1(binary_numbers: &[String]) -> u64 {
-    let (zero_counts, one_counts) = counts(binary_numbers);
-    gamma_rate(&zero_counts, &one_counts) * epsilon_rate(&zero_counts, &one_counts)
+fn part1(report: &DiagnosticReport) -> BitSet {
+    report.gamma_rate().mul(&report.epsilon_rate())
 }
 
-fn part2(binary_numbers: &[String]) -> u64 {
-    oxygen_rate(binary_numbers, 0) * co2_rate(binary_numbers, 0)
+fn part2(report: &DiagnosticReport) -> BitSet {
+    report.oxygen_rate().mul(&report.co2_rate())
 }
 
-fn counts(binary_numbers: &[String]) -> (Vec<u64>, Vec<u64>) {
-    let num_digits = binary_numbers[0].len();
-    let mut zero_counts: Vec<u64> = vec![0; num_digits];
-    let mut one_counts: Vec<u64> = vec![0; num_digits];
+const WORD_BITS: usize = u64::BITS as usize;
 
-    for binary_number in binary_numbers {
-        for (i, digit) in binary_number.chars().enumerate() {
-            match digit {
-                '0' => zero_counts[i] += 1,
-                '1' => one_counts[i] += 1,
-                _ => {}
+/// A fixed-width string of bits packed into `u64` words, least significant
+/// bit first. It doubles as an unsigned integer of arbitrary width.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    fn with_len(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(WORD_BITS)],
+            len,
+        }
+    }
+
+    fn ones(len: usize) -> Self {
+        let mut bits = Self::with_len(len);
+        bits.words.iter_mut().for_each(|word| *word = u64::MAX);
+        bits.clear_padding();
+        bits
+    }
+
+    fn set(&mut self, idx: usize) {
+        self.words[idx / WORD_BITS] |= 1 << (idx % WORD_BITS);
+    }
+
+    fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    fn and(&self, other: &Self) -> Self {
+        let words = self
+            .words
+            .iter()
+            .zip(other.words.iter())
+            .map(|(a, b)| a & b)
+            .collect();
+        Self {
+            words,
+            len: self.len.min(other.len),
+        }
+    }
+
+    fn clear_padding(&mut self) {
+        let used = self.len % WORD_BITS;
+        if used != 0 {
+            if let Some(last) = self.words.last_mut() {
+                *last &= (1 << used) - 1;
             }
         }
     }
-    (zero_counts, one_counts)
-}
 
-fn gamma_rate(zero_counts: &[u64], one_counts: &[u64]) -> u64 {
-    let mut gamma = String::with_capacity(zero_counts.len());
-    for (zeros, ones) in zero_counts.iter().zip(one_counts.iter()) {
-        if zeros > ones {
-            gamma.push('0');
-        } else {
-            gamma.push('1');
+    fn is_zero(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+
+    /// Schoolbook multiplication, treating both sets as unsigned integers.
+    fn mul(&self, other: &Self) -> Self {
+        let mut product = Self::with_len(self.len + other.len);
+        for (i, &a) in self.words.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, &b) in other.words.iter().enumerate() {
+                let current =
+                    u128::from(product.words[i + j]) + u128::from(a) * u128::from(b) + carry;
+                product.words[i + j] = low_word(current);
+                carry = current >> 64;
+            }
+            let mut k = i + other.words.len();
+            while carry != 0 {
+                let current = u128::from(product.words[k]) + carry;
+                product.words[k] = low_word(current);
+                carry = current >> 64;
+                k += 1;
+            }
         }
+        product
     }
-    u64::from_str_radix(&gamma, 2).unwrap_or_default()
-}
 
-fn epsilon_rate(zero_counts: &[u64], one_counts: &[u64]) -> u64 {
-    let mut epsilon = String::with_capacity(zero_counts.len());
-    for (zeros, ones) in zero_counts.iter().zip(one_counts.iter()) {
-        if zeros < ones {
-            epsilon.push('0');
-        } else {
-            epsilon.push('1');
+    /// Divides in place by a single word, returning the remainder.
+    fn div_rem_word(&mut self, divisor: u64) -> u64 {
+        let mut remainder = 0u128;
+        for word in self.words.iter_mut().rev() {
+            let current = remainder << 64 | u128::from(*word);
+            *word = low_word(current / u128::from(divisor));
+            remainder = current % u128::from(divisor);
         }
+        low_word(remainder)
     }
-    u64::from_str_radix(&epsilon, 2).unwrap_or_default()
 }
 
-fn oxygen_rate(binary_numbers: &[String], idx: usize) -> u64 {
-    if binary_numbers.len() == 1 {
-        return u64::from_str_radix(&binary_numbers[0], 2).unwrap_or_default();
+#[allow(clippy::cast_possible_truncation)]
+fn low_word(n: u128) -> u64 {
+    n as u64
+}
+
+impl fmt::Display for BitSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut n = self.clone();
+        let mut chunks = vec![];
+        loop {
+            chunks.push(n.div_rem_word(CHUNK));
+            if n.is_zero() {
+                break;
+            }
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap_or(&0))?;
+        for chunk in chunks {
+            write!(f, "{chunk:019}")?;
+        }
+        Ok(())
     }
+}
 
-    let (zero_counts, one_counts) = counts(binary_numbers);
-    let binary_numbers: Vec<String> = binary_numbers
-        .iter()
-        .cloned()
-        .filter(|number| {
-            let number: Vec<char> = number.chars().take(idx + 1).collect();
-            if zero_counts[idx] > one_counts[idx] {
-                number[idx] == '0'
-            } else {
-                number[idx] == '1'
+/// The diagnostic report, stored both row-wise (each number as an integer)
+/// and column-wise (for each bit position, the set of rows with a `1`), so
+/// that column counts are popcounts over the column sets. A trie over the
+/// rows drives the rating selection.
+#[derive(Debug, Clone, Default)]
+struct DiagnosticReport {
+    width: usize,
+    numbers: Vec<BitSet>,
+    columns: Vec<BitSet>,
+    trie: DiagnosticTrie,
+}
+
+impl DiagnosticReport {
+    fn from_lines<I>(lines: I) -> Result<Self, String>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let lines: Vec<String> = lines
+            .into_iter()
+            .map(|line| line.as_ref().trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let width = lines.first().map_or(0, String::len);
+
+        let mut numbers = Vec::with_capacity(lines.len());
+        let mut trie = DiagnosticTrie::default();
+        let mut columns = vec![BitSet::with_len(lines.len()); width];
+        for (row, line) in lines.iter().enumerate() {
+            if line.len() != width {
+                return Err(format!(
+                    "line {} has {} bits, expected {}",
+                    row + 1,
+                    line.len(),
+                    width
+                ));
+            }
+            let mut number = BitSet::with_len(width);
+            for (col, digit) in line.chars().enumerate() {
+                match digit {
+                    '0' => {}
+                    '1' => {
+                        number.set(width - 1 - col);
+                        columns[col].set(row);
+                    }
+                    _ => return Err(format!("line {}: '{}' is not a bit", row + 1, digit)),
+                }
             }
+            trie.insert(line.chars().map(|digit| digit == '1'), row);
+            numbers.push(number);
+        }
+
+        Ok(Self {
+            width,
+            numbers,
+            columns,
+            trie,
         })
-        .collect();
-    oxygen_rate(&binary_numbers, idx + 1)
+    }
+
+    fn all_rows(&self) -> BitSet {
+        BitSet::ones(self.numbers.len())
+    }
+
+    /// Counts of `(zeros, ones)` in column `col` among the given rows.
+    fn counts(&self, col: usize, rows: &BitSet) -> (usize, usize) {
+        let ones = self.columns[col].and(rows).count_ones();
+        (rows.count_ones() - ones, ones)
+    }
+
+    /// Builds a number from the bit chosen by `criteria` in each column.
+    fn select_by_criteria(&self, criteria: BitCriteria) -> BitSet {
+        let rows = self.all_rows();
+        let mut rate = BitSet::with_len(self.width);
+        for col in 0..self.width {
+            let (zeros, ones) = self.counts(col, &rows);
+            if criteria.keep_ones(zeros, ones) {
+                rate.set(self.width - 1 - col);
+            }
+        }
+        rate
+    }
+
+    /// Narrows the report one column at a time to the numbers whose bit
+    /// matches `criteria`, stopping when a single number is left.
+    fn filter_by_criteria(&self, criteria: BitCriteria) -> BitSet {
+        self.trie
+            .walk(|zeros, ones| criteria.keep_ones(zeros, ones))
+            .map_or_else(
+                || BitSet::with_len(self.width),
+                |row| self.numbers[row].clone(),
+            )
+    }
+
+    fn gamma_rate(&self) -> BitSet {
+        self.select_by_criteria(BitCriteria::GAMMA)
+    }
+
+    fn epsilon_rate(&self) -> BitSet {
+        self.select_by_criteria(BitCriteria::EPSILON)
+    }
+
+    fn oxygen_rate(&self) -> BitSet {
+        self.filter_by_criteria(BitCriteria::OXYGEN)
+    }
+
+    fn co2_rate(&self) -> BitSet {
+        self.filter_by_criteria(BitCriteria::CO2)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Commonality {
+    Most,
+    Least,
+}
+
+/// Which bit to choose given the counts of each in a column: the most or
+/// least common one, or `tie` when both are equally common.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BitCriteria {
+    commonality: Commonality,
+    tie: bool,
+}
+
+impl BitCriteria {
+    const GAMMA: Self = Self::new(Commonality::Most, true);
+    const EPSILON: Self = Self::new(Commonality::Least, true);
+    const OXYGEN: Self = Self::new(Commonality::Most, true);
+    const CO2: Self = Self::new(Commonality::Least, false);
+
+    const fn new(commonality: Commonality, tie: bool) -> Self {
+        Self { commonality, tie }
+    }
+
+    fn keep_ones(self, zeros: usize, ones: usize) -> bool {
+        match (self.commonality, zeros.cmp(&ones)) {
+            (_, Ordering::Equal) => self.tie,
+            (Commonality::Most, order) => order == Ordering::Less,
+            (Commonality::Least, order) => order == Ordering::Greater,
+        }
+    }
+}
+
+impl FromStr for BitCriteria {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (commonality, tie) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected <most|least>:<0|1>, got {s}"))?;
+        let commonality = match commonality {
+            "most" => Commonality::Most,
+            "least" => Commonality::Least,
+            _ => return Err(format!("unknown commonality: {commonality}")),
+        };
+        let tie = match tie {
+            "0" => false,
+            "1" => true,
+            _ => return Err(format!("tie must be 0 or 1, got {tie}")),
+        };
+        Ok(Self::new(commonality, tie))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: [Option<usize>; 2],
+    count: usize,
+    row: Option<usize>,
+}
+
+/// A binary trie over the report's numbers, most significant bit first,
+/// where every node knows how many numbers share its prefix.
+#[derive(Debug, Clone)]
+struct DiagnosticTrie {
+    nodes: Vec<TrieNode>,
 }
 
-fn co2_rate(binary_numbers: &[String], idx: usize) -> u64 {
-    if binary_numbers.len() == 1 {
-        return u64::from_str_radix(&binary_numbers[0], 2).unwrap_or_default();
+impl Default for DiagnosticTrie {
+    fn default() -> Self {
+        Self {
+            nodes: vec![TrieNode::default()],
+        }
     }
+}
 
-    let (zero_counts, one_counts) = counts(binary_numbers);
-    let binary_numbers: Vec<String> = binary_numbers
-        .iter()
-        .cloned()
-        .filter(|number| {
-            let number: Vec<char> = number.chars().take(idx + 1).collect();
-            if zero_counts[idx] <= one_counts[idx] {
-                number[idx] == '0'
+impl DiagnosticTrie {
+    fn insert<I>(&mut self, bits: I, row: usize)
+    where
+        I: IntoIterator<Item = bool>,
+    {
+        let mut node = 0;
+        self.nodes[node].count += 1;
+        for bit in bits {
+            node = if let Some(next) = self.nodes[node].children[usize::from(bit)] {
+                next
             } else {
-                number[idx] == '1'
-            }
-        })
-        .collect();
-    co2_rate(&binary_numbers, idx + 1)
+                self.nodes.push(TrieNode::default());
+                let next = self.nodes.len() - 1;
+                self.nodes[node].children[usize::from(bit)] = Some(next);
+                next
+            };
+            self.nodes[node].count += 1;
+        }
+        self.nodes[node].row.get_or_insert(row);
+    }
+
+    /// Walks from the root to a leaf, at each level following the `1` branch
+    /// when `keep_ones(zeros, ones)` holds for the counts below the current
+    /// node. An empty branch is never taken, so the walk always ends on a
+    /// number in the report. Returns that number's row.
+    fn walk<F>(&self, keep_ones: F) -> Option<usize>
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        if self.nodes[0].count == 0 {
+            return None;
+        }
+        let mut node = 0;
+        loop {
+            node = match self.nodes[node].children {
+                [None, None] => break,
+                [Some(child), None] | [None, Some(child)] => child,
+                [Some(zeros_child), Some(ones_child)] => {
+                    let zeros = self.nodes[zeros_child].count;
+                    let ones = self.nodes[ones_child].count;
+                    if keep_ones(zeros, ones) {
+                        ones_child
+                    } else {
+                        zeros_child
+                    }
+                }
+            };
+        }
+        self.nodes[node].row
+    }
+
+    fn find(&self, prefix: &str) -> Option<usize> {
+        let mut node = 0;
+        for digit in prefix.chars() {
+            let bit = match digit {
+                '0' => 0,
+                '1' => 1,
+                _ => return None,
+            };
+            node = self.nodes[node].children[bit]?;
+        }
+        Some(node)
+    }
+
+    /// How many numbers in the report start with `prefix`.
+    fn count_with_prefix(&self, prefix: &str) -> usize {
+        self.find(prefix).map_or(0, |node| self.nodes[node].count)
+    }
+
+    /// The prefix of length `len` shared by the most numbers, with its count.
+    /// Ties go to the lexicographically larger prefix.
+    fn most_common_prefix(&self, len: usize) -> Option<(String, usize)> {
+        let mut level = vec![(String::new(), 0)];
+        for _ in 0..len {
+            level = level
+                .iter()
+                .flat_map(|(prefix, node)| {
+                    self.nodes[*node]
+                        .children
+                        .iter()
+                        .zip(['0', '1'])
+                        .filter_map(move |(child, digit)| {
+                            child.map(|child| (format!("{prefix}{digit}"), child))
+                        })
+                })
+                .collect();
+        }
+        level
+            .into_iter()
+            .map(|(prefix, node)| (prefix, self.nodes[node].count))
+            .filter(|&(_, count)| count > 0)
+            .max_by_key(|(prefix, count)| (*count, prefix.clone()))
+    }
 }
 
 #[cfg(test)]
@@ -110,15 +457,78 @@ mod day3_tests {
         "00010", "01010",
     ];
 
+    fn example_report() -> DiagnosticReport {
+        DiagnosticReport::from_lines(EXAMPLE_INPUT).unwrap()
+    }
+
     #[test]
     fn part1_example() {
-        let result = part1(&EXAMPLE_INPUT.map(|line| line.to_string()));
-        assert_eq!(result, 198);
+        let result = part1(&example_report());
+        assert_eq!(result.to_string(), "198");
     }
 
     #[test]
     fn part2_example() {
-        let result = part2(&EXAMPLE_INPUT.map(|line| line.to_string()));
-        assert_eq!(result, 230);
+        let result = part2(&example_report());
+        assert_eq!(result.to_string(), "230");
+    }
+
+    #[test]
+    fn test_wide_report() {
+        let high = format!("1{}", "0".repeat(99));
+        let low = format!("{}1", "0".repeat(99));
+        let report = DiagnosticReport::from_lines([&high, &high, &low]).unwrap();
+        assert_eq!(
+            report.gamma_rate().to_string(),
+            "633825300114114700748351602688"
+        );
+        assert_eq!(
+            report.epsilon_rate().to_string(),
+            "633825300114114700748351602687"
+        );
+        assert_eq!(report.oxygen_rate(), report.gamma_rate());
+        assert_eq!(report.co2_rate().to_string(), "1");
+        assert_eq!(
+            part1(&report).to_string(),
+            "401734511064747568885490523084656825330436633744949857222656"
+        );
+    }
+
+    #[test]
+    fn test_trie_prefix_queries() {
+        let trie = example_report().trie;
+        assert_eq!(trie.count_with_prefix(""), 12);
+        assert_eq!(trie.count_with_prefix("1"), 7);
+        assert_eq!(trie.count_with_prefix("10"), 4);
+        assert_eq!(trie.count_with_prefix("101"), 3);
+        assert_eq!(trie.count_with_prefix("111"), 2);
+        assert_eq!(trie.count_with_prefix("0000"), 0);
+        assert_eq!(trie.most_common_prefix(3), Some(("101".to_string(), 3)));
+        assert_eq!(trie.most_common_prefix(6), None);
+    }
+
+    #[test]
+    fn test_criteria_ties() {
+        let report = DiagnosticReport::from_lines(["10", "01", "11", "00"]).unwrap();
+        let most_zero = BitCriteria::new(Commonality::Most, false);
+        let least_one = BitCriteria::new(Commonality::Least, true);
+        assert_eq!(report.select_by_criteria(most_zero).to_string(), "0");
+        assert_eq!(report.select_by_criteria(least_one).to_string(), "3");
+        assert_eq!(report.filter_by_criteria(most_zero).to_string(), "0");
+        assert_eq!(report.filter_by_criteria(least_one).to_string(), "3");
+    }
+
+    #[test]
+    fn test_parse_criteria() {
+        assert_eq!("most:1".parse(), Ok(BitCriteria::OXYGEN));
+        assert_eq!("least:0".parse(), Ok(BitCriteria::CO2));
+        assert!("often:1".parse::<BitCriteria>().is_err());
+        assert!("most".parse::<BitCriteria>().is_err());
+    }
+
+    #[test]
+    fn test_ragged_report() {
+        let result = DiagnosticReport::from_lines(["0101", "011"]);
+        assert!(result.is_err());
     }
 }
@@ -1,83 +1,228 @@
 #![warn(clippy::all, clippy::pedantic)]
-use std::collections::HashMap;
+use aoc2021::rng::Rng;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::str::FromStr;
 
 fn main() {
+    let mut win_rule = WinRule::default();
+    let mut show_events = false;
+    let mut simulation: Option<(usize, u64)> = None;
+    let mut args = env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "events" => show_events = true,
+            "simulate" => {
+                // Trials and seed are optional, so a following win rule is
+                // left for the next iteration.
+                let is_number = |n: &String| n.parse::<u64>().is_ok();
+                let trials = args.next_if(is_number).map_or(Ok(1000), |n| n.parse());
+                let seed = args.next_if(is_number).map_or(Ok(0), |n| n.parse());
+                simulation = Some((
+                    trials.expect("trials must be a number"),
+                    seed.expect("seed must be a number"),
+                ));
+            }
+            rule => win_rule = rule.parse().expect("invalid win rule"),
+        }
+    }
     let input_lines: Vec<String> = aoc2021::input_lines().collect();
-    let draws: Vec<u64> = input_lines[0]
-        .trim()
-        .split(',')
-        .map(|n| n.parse::<u64>().unwrap())
+    let (draws, boards) = parse_input(&input_lines).expect("invalid input");
+    let boards: Vec<Board> = boards
+        .into_iter()
+        .map(|board| board.with_win_rule(win_rule))
         .collect();
 
-    let mut boards = build_boards(
-        &input_lines[1..]
-            .chunks(6)
-            .map(|bar| bar[1..].join("\n").trim().to_string())
-            .collect::<Vec<String>>(),
-    );
+    let result = part1(&boards, &draws);
+    println!("Part 1: {result}");
+    let result = part2(&boards, &draws);
+    println!("Part 2: {result}");
 
-    let mut boards_cloned = boards.clone();
+    if show_events {
+        for event in BingoGame::new(&boards, &draws).play() {
+            println!(
+                "#{} board {} on draw {} ({}) with {:?}, score {}{}",
+                event.rank,
+                event.board,
+                event.draw_index,
+                event.number,
+                event.line,
+                event.score,
+                if event.tied { " (tied)" } else { "" }
+            );
+        }
+    }
 
-    let result = part1(&mut boards, &draws);
-    println!("Part 1: {}", result);
-    let result = part2(&mut boards_cloned, &draws);
-    println!("Part 2: {}", result);
+    if let Some((trials, seed)) = simulation {
+        for (idx, odds) in simulate(&boards, &draws, trials, seed).iter().enumerate() {
+            println!(
+                "Board {idx}: first {:.4}, last {:.4}, median draw {}",
+                odds.first_probability(),
+                odds.last_probability(),
+                odds.median_draw_index()
+                    .map_or_else(|| "-".to_string(), |i| i.to_string())
+            );
+            println!("  wins by draw: {}", odds.win_distribution());
+        }
+    }
 }
 
-fn build_boards(board_strings: &[String]) -> Vec<Board> {
-    board_strings
-        .iter()
-        .map(|board| Board::from_str(board).unwrap())
-        .collect()
+fn parse_input(lines: &[String]) -> Result<(Vec<u64>, Vec<Board>), String> {
+    let (first, rest) = lines.split_first().ok_or("no draws")?;
+    let draws = first
+        .trim()
+        .split(',')
+        .map(|n| n.parse::<u64>().map_err(|e| format!("bad draw {n}: {e}")))
+        .collect::<Result<Vec<u64>, String>>()?;
+
+    let boards = rest
+        .split(|line| line.trim().is_empty())
+        .filter(|rows| !rows.is_empty())
+        .map(|rows| Board::from_str(&rows.join("\n")))
+        .collect::<Result<Vec<Board>, String>>()?;
+
+    Ok((draws, boards))
 }
 
-const BINGO_SIZE: usize = 5;
+/// When a board counts as having won.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum WinRule {
+    /// Any complete row or column.
+    #[default]
+    Lines,
+    /// Any complete row, column or, on square boards, diagonal.
+    LinesAndDiagonals,
+    /// Every number on the board is marked.
+    FullCard,
+}
+
+impl FromStr for WinRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(WinRule::Lines),
+            "diagonals" => Ok(WinRule::LinesAndDiagonals),
+            "full" => Ok(WinRule::FullCard),
+            _ => Err(format!("unknown win rule: {s}")),
+        }
+    }
+}
 
 #[derive(Default, Clone)]
 struct Board {
     numbers: HashMap<u64, (usize, usize)>,
-    row_marks: [u8; BINGO_SIZE],
-    col_marks: [u8; BINGO_SIZE],
+    rows: usize,
+    cols: usize,
+    row_marks: Vec<usize>,
+    col_marks: Vec<usize>,
+    diagonal_marks: [usize; 2],
+    marked: HashSet<u64>,
     unmarked_sum: u64,
+    win_rule: WinRule,
 }
 
 impl Board {
-    pub fn new(input_rows: &[Vec<u64>]) -> Self {
-        let mut numbers = HashMap::with_capacity(BINGO_SIZE * BINGO_SIZE);
+    pub fn new(input_rows: &[Vec<u64>]) -> Result<Self, String> {
+        let rows = input_rows.len();
+        let cols = input_rows.first().map_or(0, Vec::len);
+        if rows == 0 || cols == 0 {
+            return Err("board is empty".to_string());
+        }
+
+        let mut numbers = HashMap::with_capacity(rows * cols);
         let mut unmarked_sum = 0;
-        for (row_idx, row) in input_rows.iter().enumerate().take(BINGO_SIZE) {
-            for (col_idx, number) in row.iter().enumerate().take(BINGO_SIZE) {
-                numbers.insert(*number, (row_idx, col_idx));
+        for (row_idx, row) in input_rows.iter().enumerate() {
+            if row.len() != cols {
+                return Err(format!(
+                    "row {} has {} numbers, expected {}",
+                    row_idx + 1,
+                    row.len(),
+                    cols
+                ));
+            }
+            for (col_idx, number) in row.iter().enumerate() {
+                if numbers.insert(*number, (row_idx, col_idx)).is_some() {
+                    return Err(format!("{number} appears on the board more than once"));
+                }
                 unmarked_sum += number;
             }
         }
-        Self {
+        Ok(Self {
             numbers,
-            row_marks: Default::default(),
-            col_marks: Default::default(),
+            rows,
+            cols,
+            row_marks: vec![0; rows],
+            col_marks: vec![0; cols],
+            diagonal_marks: [0; 2],
+            marked: HashSet::with_capacity(rows * cols),
             unmarked_sum,
-        }
+            win_rule: WinRule::default(),
+        })
     }
 
-    pub fn mark(&mut self, draw: u64) -> Option<u64> {
-        if let Some((row, col)) = self.numbers.get(&draw) {
-            self.row_marks[*row] += 1;
-            self.col_marks[*col] += 1;
-            self.unmarked_sum -= draw;
+    pub fn with_win_rule(self, win_rule: WinRule) -> Self {
+        Self { win_rule, ..self }
+    }
 
-            let row_count = self.row_marks[*row] as usize;
-            let col_count = self.col_marks[*col] as usize;
+    fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
 
-            if row_count == BINGO_SIZE || col_count == BINGO_SIZE {
-                return Some(self.unmarked_sum * draw);
+    /// Marks `draw` on the board, returning the winning line and score if
+    /// that mark makes the board win. Drawing a number that is already
+    /// marked changes nothing.
+    pub fn mark(&mut self, draw: u64) -> Option<(WinningLine, u64)> {
+        if let Some(&(row, col)) = self.numbers.get(&draw) {
+            if !self.marked.insert(draw) {
+                return None;
+            }
+            self.row_marks[row] += 1;
+            self.col_marks[col] += 1;
+            if self.is_square() && row == col {
+                self.diagonal_marks[0] += 1;
             }
+            if self.is_square() && row + col + 1 == self.cols {
+                self.diagonal_marks[1] += 1;
+            }
+            self.unmarked_sum -= draw;
+
+            let completed_line = if self.row_marks[row] == self.cols {
+                Some(WinningLine::Row(row))
+            } else if self.col_marks[col] == self.rows {
+                Some(WinningLine::Column(col))
+            } else {
+                None
+            };
+            let completed_diagonal = if !self.is_square() {
+                None
+            } else if row == col && self.diagonal_marks[0] == self.rows {
+                Some(WinningLine::Diagonal)
+            } else if row + col + 1 == self.cols && self.diagonal_marks[1] == self.rows {
+                Some(WinningLine::AntiDiagonal)
+            } else {
+                None
+            };
+            let winning_line = match self.win_rule {
+                WinRule::Lines => completed_line,
+                WinRule::LinesAndDiagonals => completed_line.or(completed_diagonal),
+                WinRule::FullCard => {
+                    (self.marked.len() == self.numbers.len()).then_some(WinningLine::FullCard)
+                }
+            };
+            return winning_line.map(|line| (line, self.unmarked_sum * draw));
         }
         None
     }
 
     pub fn has_won(&self) -> bool {
-        self.row_marks.iter().any(|n| *n >= 5) || self.col_marks.iter().any(|n| *n >= 5)
+        let has_line = self.row_marks.contains(&self.cols) || self.col_marks.contains(&self.rows);
+        let has_diagonal = self.is_square() && self.diagonal_marks.contains(&self.rows);
+        match self.win_rule {
+            WinRule::Lines => has_line,
+            WinRule::LinesAndDiagonals => has_line || has_diagonal,
+            WinRule::FullCard => self.marked.len() == self.numbers.len(),
+        }
     }
 }
 
@@ -85,49 +230,188 @@ impl FromStr for Board {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let rows = s.split('\n');
-        let numbers = rows
-            .map(str::trim)
-            .map(str::split_whitespace)
-            .map(|ns| ns.map(|n| n.parse::<u64>().unwrap()).collect::<Vec<u64>>())
-            .collect::<Vec<Vec<u64>>>();
+        let numbers = s
+            .trim()
+            .lines()
+            .map(|row| {
+                row.split_whitespace()
+                    .map(|n| n.parse::<u64>().map_err(|e| format!("bad number {n}: {e}")))
+                    .collect::<Result<Vec<u64>, String>>()
+            })
+            .collect::<Result<Vec<Vec<u64>>, String>>()?;
 
-        Ok(Board::new(&numbers))
+        Board::new(&numbers)
     }
 }
 
-fn part1(boards: &mut [Board], draws: &[u64]) -> u64 {
-    for draw in draws {
-        for board in &mut *boards {
-            if let Some(score) = board.mark(*draw) {
-                return score;
+/// The marks that made a board win.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WinningLine {
+    Row(usize),
+    Column(usize),
+    Diagonal,
+    AntiDiagonal,
+    FullCard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WinEvent {
+    board: usize,
+    draw_index: usize,
+    number: u64,
+    line: WinningLine,
+    score: u64,
+    /// Boards that won on the same draw share a rank.
+    rank: usize,
+    tied: bool,
+}
+
+struct BingoGame {
+    boards: Vec<Board>,
+    draws: Vec<u64>,
+}
+
+impl BingoGame {
+    fn new(boards: &[Board], draws: &[u64]) -> Self {
+        Self {
+            boards: boards.to_vec(),
+            draws: draws.to_vec(),
+        }
+    }
+
+    /// Plays every draw, returning each board's win in the order they
+    /// happen. Boards stop playing once they have won.
+    fn play(&self) -> Vec<WinEvent> {
+        let mut boards = self.boards.clone();
+        let mut events: Vec<WinEvent> = vec![];
+        for (draw_index, &number) in self.draws.iter().enumerate() {
+            let first_of_draw = events.len();
+            for (idx, board) in boards.iter_mut().enumerate() {
+                if board.has_won() {
+                    continue;
+                }
+                if let Some((line, score)) = board.mark(number) {
+                    events.push(WinEvent {
+                        board: idx,
+                        draw_index,
+                        number,
+                        line,
+                        score,
+                        rank: first_of_draw + 1,
+                        tied: false,
+                    });
+                }
+            }
+            if events.len() - first_of_draw > 1 {
+                for event in &mut events[first_of_draw..] {
+                    event.tied = true;
+                }
             }
         }
+        events
     }
-    0
 }
 
-fn part2(boards: &mut [Board], draws: &[u64]) -> u64 {
-    for draw in draws {
-        let mut count: u64 = 0;
-        let mut last_result: Option<u64> = None;
-        for board in &mut *boards {
-            if !board.has_won() {
-                count += 1;
-                last_result = board.mark(*draw);
-            }
+/// How a board fared over many games with shuffled draws.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BoardOdds {
+    trials: usize,
+    /// Games where the board won first, including ties for first.
+    won_first: usize,
+    /// Games where the board won last, including ties for last.
+    won_last: usize,
+    /// How many games the board won at each draw index.
+    win_draws: Vec<usize>,
+}
+
+impl BoardOdds {
+    fn new(trials: usize, draw_count: usize) -> Self {
+        Self {
+            trials,
+            won_first: 0,
+            won_last: 0,
+            win_draws: vec![0; draw_count],
         }
-        if count == 1 {
-            if let Some(score) = last_result {
-                return score;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn first_probability(&self) -> f64 {
+        self.won_first as f64 / self.trials as f64
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn last_probability(&self) -> f64 {
+        self.won_last as f64 / self.trials as f64
+    }
+
+    /// The draw index by which the board had won in half of all games.
+    fn median_draw_index(&self) -> Option<usize> {
+        let mut seen = 0;
+        self.win_draws.iter().position(|&count| {
+            seen += count;
+            seen * 2 >= self.trials
+        })
+    }
+
+    /// The draw indexes the board won at, as `draw:count` for each index
+    /// with at least one win.
+    fn win_distribution(&self) -> String {
+        let buckets: Vec<String> = self
+            .win_draws
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(draw, count)| format!("{draw}:{count}"))
+            .collect();
+        if buckets.is_empty() {
+            "-".to_string()
+        } else {
+            buckets.join(" ")
+        }
+    }
+}
+
+/// Plays `trials` games with the draws shuffled by a generator seeded with
+/// `seed`, tallying each board's results.
+fn simulate(boards: &[Board], draws: &[u64], trials: usize, seed: u64) -> Vec<BoardOdds> {
+    let mut rng = Rng::new(seed);
+    let mut odds = vec![BoardOdds::new(trials, draws.len()); boards.len()];
+    let mut game = BingoGame::new(boards, draws);
+    for _ in 0..trials {
+        rng.shuffle(&mut game.draws);
+        let events = game.play();
+        let first_rank = events.first().map(|event| event.rank);
+        let last_rank = events.last().map(|event| event.rank);
+        for event in &events {
+            let board = &mut odds[event.board];
+            board.win_draws[event.draw_index] += 1;
+            if Some(event.rank) == first_rank {
+                board.won_first += 1;
+            }
+            if Some(event.rank) == last_rank {
+                board.won_last += 1;
             }
         }
     }
-    0
+    odds
+}
+
+fn part1(boards: &[Board], draws: &[u64]) -> u64 {
+    BingoGame::new(boards, draws)
+        .play()
+        .first()
+        .map_or(0, |event| event.score)
+}
+
+fn part2(boards: &[Board], draws: &[u64]) -> u64 {
+    BingoGame::new(boards, draws)
+        .play()
+        .last()
+        .map_or(0, |event| event.score)
 }
 
 #[cfg(test)]
-mod day3_tests {
+mod day4_tests {
     use super::*;
 
     const EXAMPLE_DRAWS: [u64; 27] = [
@@ -137,41 +421,206 @@ mod day3_tests {
 
     fn example_boards() -> [Board; 3] {
         [
-            Board::new(&vec![
+            Board::new(&[
                 vec![22, 13, 17, 11, 0],
                 vec![8, 2, 23, 4, 24],
                 vec![21, 9, 14, 16, 7],
                 vec![6, 10, 3, 18, 5],
                 vec![1, 12, 20, 15, 19],
-            ]),
-            Board::new(&vec![
+            ])
+            .unwrap(),
+            Board::new(&[
                 vec![3, 15, 0, 2, 22],
                 vec![9, 18, 13, 17, 5],
                 vec![19, 8, 7, 25, 23],
                 vec![20, 11, 10, 24, 4],
                 vec![14, 21, 16, 12, 6],
-            ]),
-            Board::new(&vec![
+            ])
+            .unwrap(),
+            Board::new(&[
                 vec![14, 21, 17, 24, 4],
                 vec![10, 16, 15, 9, 19],
                 vec![18, 8, 23, 26, 20],
                 vec![22, 11, 13, 6, 5],
                 vec![2, 0, 12, 3, 7],
-            ]),
+            ])
+            .unwrap(),
         ]
     }
 
     #[test]
     fn part1_example() {
-        let mut boards = example_boards();
-        let result = part1(&mut boards, &EXAMPLE_DRAWS);
+        let boards = example_boards();
+        let result = part1(&boards, &EXAMPLE_DRAWS);
         assert_eq!(result, 4512);
     }
 
     #[test]
     fn part2_example() {
-        let mut boards = example_boards();
-        let result = part2(&mut boards, &EXAMPLE_DRAWS);
+        let boards = example_boards();
+        let result = part2(&boards, &EXAMPLE_DRAWS);
         assert_eq!(result, 1924);
     }
+
+    #[test]
+    fn test_game_events() {
+        let events = BingoGame::new(&example_boards(), &EXAMPLE_DRAWS).play();
+        let summary: Vec<(usize, usize, u64, WinningLine)> = events
+            .iter()
+            .map(|e| (e.board, e.draw_index, e.number, e.line))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (2, 11, 24, WinningLine::Row(0)),
+                (0, 13, 16, WinningLine::Row(2)),
+                (1, 14, 13, WinningLine::Column(2)),
+            ]
+        );
+        assert!(events.iter().all(|e| !e.tied));
+        assert_eq!(
+            events.iter().map(|e| e.rank).collect::<Vec<usize>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_game_ties() {
+        let boards = [
+            Board::from_str("1 2\n3 4").unwrap(),
+            Board::from_str("5 6\n7 8").unwrap(),
+            Board::from_str("1 5\n9 10").unwrap(),
+        ];
+        let events = BingoGame::new(&boards, &[7, 1, 5, 3]).play();
+        let summary: Vec<(usize, usize, usize, bool)> = events
+            .iter()
+            .map(|e| (e.board, e.draw_index, e.rank, e.tied))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(1, 2, 1, true), (2, 2, 1, true), (0, 3, 3, false)]
+        );
+        assert_eq!(events[1].line, WinningLine::Row(0));
+        assert_eq!(events[2].line, WinningLine::Column(0));
+    }
+
+    #[test]
+    fn test_simulate_is_reproducible() {
+        let boards = example_boards();
+        let a = simulate(&boards, &EXAMPLE_DRAWS, 200, 42);
+        let b = simulate(&boards, &EXAMPLE_DRAWS, 200, 42);
+        assert_eq!(a, b);
+        assert_ne!(a, simulate(&boards, &EXAMPLE_DRAWS, 200, 43));
+    }
+
+    #[test]
+    fn test_simulate_totals() {
+        let boards = example_boards();
+        let odds = simulate(&boards, &EXAMPLE_DRAWS, 200, 7);
+        let firsts: usize = odds.iter().map(|o| o.won_first).sum();
+        let lasts: usize = odds.iter().map(|o| o.won_last).sum();
+        assert!(firsts >= 200);
+        assert!(lasts >= 200);
+        for board in &odds {
+            assert_eq!(board.win_draws.iter().sum::<usize>(), 200);
+            assert!(board.median_draw_index().is_some());
+        }
+    }
+
+    #[test]
+    fn test_simulate_two_boards() {
+        let boards = [
+            Board::from_str("1").unwrap(),
+            Board::from_str("2 3\n4 5").unwrap(),
+        ];
+        let odds = simulate(&boards, &[1, 2, 3], 50, 1);
+        assert!((odds[0].first_probability() - odds[1].last_probability()).abs() < f64::EPSILON);
+        assert_eq!(odds[0].won_first + odds[1].won_first, 50);
+        assert_eq!(odds[1].win_draws[0], 0);
+        assert_eq!(odds[0].win_distribution(), "0:15 1:21 2:14");
+        assert_eq!(odds[1].win_distribution(), "1:14 2:36");
+    }
+
+    #[test]
+    fn test_parse_input() {
+        let lines: Vec<String> = [
+            "1,2,3", "", "1 2", "3 4", "", "", "5 6 7", "8 9 10", "11 12 0",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        let (draws, boards) = parse_input(&lines).unwrap();
+        assert_eq!(draws, vec![1, 2, 3]);
+        assert_eq!(boards.len(), 2);
+        assert_eq!((boards[0].rows, boards[0].cols), (2, 2));
+        assert_eq!((boards[1].rows, boards[1].cols), (3, 3));
+    }
+
+    #[test]
+    fn test_invalid_boards() {
+        assert!(Board::from_str("1 2 3\n4 5\n6 7 8").is_err());
+        assert!(Board::from_str("1 2\n3 1").is_err());
+        assert!(Board::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_diagonal_win() {
+        let board = Board::from_str("1 2 3\n4 5 6\n7 8 9").unwrap();
+        let mut lines = board.clone();
+        let mut diagonals = board.with_win_rule(WinRule::LinesAndDiagonals);
+        assert_eq!(None, lines.mark(3));
+        assert_eq!(None, lines.mark(5));
+        assert_eq!(None, lines.mark(7));
+        assert!(!lines.has_won());
+        assert_eq!(None, diagonals.mark(3));
+        assert_eq!(None, diagonals.mark(5));
+        assert_eq!(Some((WinningLine::AntiDiagonal, 30 * 7)), diagonals.mark(7));
+        assert!(diagonals.has_won());
+    }
+
+    #[test]
+    fn test_full_card_win() {
+        let mut board = Board::from_str("1 2\n3 4")
+            .unwrap()
+            .with_win_rule(WinRule::FullCard);
+        assert_eq!(None, board.mark(1));
+        assert_eq!(None, board.mark(2));
+        assert!(!board.has_won());
+        assert_eq!(None, board.mark(3));
+        assert_eq!(Some((WinningLine::FullCard, 0)), board.mark(4));
+        assert!(board.has_won());
+    }
+
+    #[test]
+    fn test_repeated_draw() {
+        let mut board = Board::from_str("7 1\n2 3").unwrap();
+        assert_eq!(None, board.mark(7));
+        assert_eq!(None, board.mark(7));
+        assert!(!board.has_won());
+        assert_eq!(Some((WinningLine::Row(0), 5)), board.mark(1));
+
+        let mut full = Board::from_str("1 2\n3 4")
+            .unwrap()
+            .with_win_rule(WinRule::FullCard);
+        for draw in [1, 1, 2, 2, 3] {
+            assert_eq!(None, full.mark(draw));
+        }
+        assert!(!full.has_won());
+        assert_eq!(Some((WinningLine::FullCard, 0)), full.mark(4));
+    }
+
+    #[test]
+    fn test_seven_by_seven() {
+        let rows: Vec<Vec<u64>> = (0..7)
+            .map(|r| (0..7).map(|c| r * 7 + c).collect())
+            .collect();
+        let mut board = Board::new(&rows).unwrap();
+        for n in 0..6 {
+            assert_eq!(None, board.mark(n * 7 + 3));
+        }
+        assert_eq!(
+            Some((WinningLine::Column(3), (1176 - 168) * 45)),
+            board.mark(45)
+        );
+    }
 }
@@ -1,41 +1,391 @@
 #![warn(clippy::all, clippy::pedantic)]
 use aoc2021::point::Point;
 use aoc2021::{input_lines, parse_lines};
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
 use std::collections::HashMap;
+use std::env;
 use std::num::ParseIntError;
 use std::str::FromStr;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
     let line_segments: Vec<LineSegment> = parse_lines(input_lines()).collect();
-    let result = part1(&line_segments);
-    println!("{}", result);
-    let result = part2(&line_segments);
-    println!("{}", result);
+    match args[..] {
+        ["sweep"] => {
+            let straight: Vec<LineSegment> = line_segments
+                .iter()
+                .copied()
+                .filter(|line| !line.is_diagonal())
+                .collect();
+            println!("{}", OverlapCounter::new(&straight).count_at_least(2));
+            println!("{}", OverlapCounter::new(&line_segments).count_at_least(2));
+        }
+        ["count", min, max, threshold] => {
+            let rect = Rect {
+                min: min.parse().expect("invalid corner"),
+                max: max.parse().expect("invalid corner"),
+            };
+            let threshold = threshold.parse().expect("threshold must be a number");
+            let map = OverlapMap::new(&line_segments, Rasterization::Lattice);
+            println!("{}", map.count_in(rect, threshold));
+        }
+        ["histogram"] => {
+            let map = OverlapMap::new(&line_segments, Rasterization::Lattice);
+            for (degree, points) in map.histogram().iter().enumerate().skip(1) {
+                println!("{degree}: {points}");
+            }
+        }
+        ["hottest", k] => {
+            let map = OverlapMap::new(&line_segments, Rasterization::Lattice);
+            for (point, count) in map.hottest(k.parse().expect("k must be a number")) {
+                println!("{},{}: {count}", point.x, point.y);
+            }
+        }
+        [format, ref options @ ..] if format.parse::<ImageFormat>().is_ok() => {
+            let (include_diagonals, window) = image_options(options);
+            let counts = overlap_counts(&line_segments, Rasterization::Lattice, include_diagonals);
+            let format: ImageFormat = format.parse().expect("checked above");
+            print!("{}", format.render(&counts, window));
+        }
+        _ => {
+            let rasterization = match args.first() {
+                Some(&"bresenham") => Rasterization::Bresenham,
+                _ => Rasterization::Lattice,
+            };
+            let result = part1(&line_segments, rasterization);
+            println!("{result}");
+            let result = part2(&line_segments, rasterization);
+            println!("{result}");
+        }
+    }
+}
+
+/// Reads the optional `[straight | all] [min max]` image arguments: which
+/// segments to draw, as in part 1 or part 2, and the corners to crop to.
+fn image_options(options: &[&str]) -> (bool, Option<Rect>) {
+    let (include_diagonals, crop) = match options {
+        ["straight", crop @ ..] => (false, crop),
+        ["all", crop @ ..] | crop => (true, crop),
+    };
+    let window = match crop {
+        [min, max] => Some(Rect {
+            min: min.parse().expect("invalid crop corner"),
+            max: max.parse().expect("invalid crop corner"),
+        }),
+        _ => None,
+    };
+    (include_diagonals, window)
+}
+
+fn part1(line_segments: &[LineSegment], rasterization: Rasterization) -> usize {
+    let points = overlap_counts(line_segments, rasterization, false);
+    points.values().filter(|count| **count >= 2).count()
 }
 
-fn part1(line_segments: &[LineSegment]) -> usize {
+fn part2(line_segments: &[LineSegment], rasterization: Rasterization) -> usize {
+    let points = overlap_counts(line_segments, rasterization, true);
+    points.values().filter(|count| **count >= 2).count()
+}
+
+/// How many segments cover each point, optionally skipping diagonals.
+fn overlap_counts(
+    line_segments: &[LineSegment],
+    rasterization: Rasterization,
+    include_diagonals: bool,
+) -> HashMap<Point, usize> {
     let mut points: HashMap<Point, usize> = HashMap::default();
     for line in line_segments {
-        if !line.is_diagonal() {
-            for point in line.into_iter() {
+        if include_diagonals || !line.is_diagonal() {
+            for point in line.rasterize(rasterization) {
                 let entry = points.entry(point).or_insert(0);
                 *entry += 1;
             }
         }
     }
-    points.values().filter(|count| **count >= 2).count()
+    points
 }
 
-fn part2(line_segments: &[LineSegment]) -> usize {
-    let mut points: HashMap<Point, usize> = HashMap::default();
-    for line in line_segments {
-        for point in line.into_iter() {
-            let entry = points.entry(point).or_insert(0);
-            *entry += 1;
+/// An axis-aligned rectangle, inclusive of both corners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    min: Point,
+    max: Point,
+}
+
+impl Rect {
+    fn bounding<'a, I>(points: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a Point>,
+    {
+        points.into_iter().fold(None, |rect, &point| {
+            Some(rect.map_or(
+                Rect {
+                    min: point,
+                    max: point,
+                },
+                |rect: Rect| Rect {
+                    min: Point {
+                        x: rect.min.x.min(point.x),
+                        y: rect.min.y.min(point.y),
+                    },
+                    max: Point {
+                        x: rect.max.x.max(point.x),
+                        y: rect.max.y.max(point.y),
+                    },
+                },
+            ))
+        })
+    }
+
+    fn width(&self) -> usize {
+        usize::try_from(self.max.x - self.min.x + 1).unwrap_or(0)
+    }
+
+    fn height(&self) -> usize {
+        usize::try_from(self.max.y - self.min.y + 1).unwrap_or(0)
+    }
+
+    fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = Point>> {
+        let (min, max) = (self.min, self.max);
+        (min.y..=max.y).map(move |y| (min.x..=max.x).map(move |x| Point { x, y }))
+    }
+}
+
+/// Draws the counts the way the puzzle does: `.` for uncovered points and
+/// the count otherwise, or `#` once it no longer fits in a digit.
+fn render_ascii(counts: &HashMap<Point, usize>, window: Option<Rect>) -> String {
+    let mut diagram = String::new();
+    if let Some(window) = window.or_else(|| Rect::bounding(counts.keys())) {
+        for row in window.rows() {
+            for point in row {
+                diagram.push(match counts.get(&point) {
+                    None | Some(0) => '.',
+                    Some(&count) => u32::try_from(count)
+                        .ok()
+                        .and_then(|count| char::from_digit(count, 10))
+                        .unwrap_or('#'),
+                });
+            }
+            diagram.push('\n');
+        }
+    }
+    diagram
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageFormat {
+    Ascii,
+    /// Plain (`P2`) grayscale netpbm.
+    Pgm,
+    /// Plain (`P3`) color netpbm, shaded as a heatmap.
+    Ppm,
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(ImageFormat::Ascii),
+            "pgm" => Ok(ImageFormat::Pgm),
+            "ppm" => Ok(ImageFormat::Ppm),
+            _ => Err(format!("unknown image format: {s}")),
+        }
+    }
+}
+
+impl ImageFormat {
+    fn render(self, counts: &HashMap<Point, usize>, window: Option<Rect>) -> String {
+        match self {
+            ImageFormat::Ascii => render_ascii(counts, window),
+            ImageFormat::Pgm | ImageFormat::Ppm => render_netpbm(counts, self, window),
         }
     }
-    points.values().filter(|count| **count >= 2).count()
+}
+
+/// Writes the counts as a plain-text netpbm image, one pixel per point.
+fn render_netpbm(
+    counts: &HashMap<Point, usize>,
+    format: ImageFormat,
+    window: Option<Rect>,
+) -> String {
+    const MAX_LINE: usize = 70;
+    let window = window
+        .or_else(|| Rect::bounding(counts.keys()))
+        .unwrap_or(Rect {
+            min: Point { x: 0, y: 0 },
+            max: Point { x: -1, y: -1 },
+        });
+    let max_count = window
+        .rows()
+        .flatten()
+        .filter_map(|point| counts.get(&point))
+        .max()
+        .copied()
+        .unwrap_or(0)
+        .clamp(1, usize::from(u16::MAX));
+
+    let (magic, maxval) = match format {
+        ImageFormat::Ppm => ("P3", 255),
+        _ => ("P2", max_count),
+    };
+    let mut image = format!(
+        "{magic}\n{} {}\n{maxval}\n",
+        window.width(),
+        window.height()
+    );
+    for row in window.rows() {
+        let mut line = String::new();
+        for point in row {
+            let count = counts.get(&point).copied().unwrap_or(0).min(max_count);
+            let samples = match format {
+                ImageFormat::Ppm => {
+                    let (r, g, b) = heat_color(count, max_count);
+                    format!("{r} {g} {b}")
+                }
+                _ => count.to_string(),
+            };
+            if !line.is_empty() && line.len() + 1 + samples.len() > MAX_LINE {
+                image.push_str(&line);
+                image.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&samples);
+        }
+        image.push_str(&line);
+        image.push('\n');
+    }
+    image
+}
+
+/// Shades `count` from black through red and yellow to white.
+fn heat_color(count: usize, max_count: usize) -> (u8, u8, u8) {
+    let level = count * 765 / max_count.max(1);
+    let channel =
+        |offset: usize| u8::try_from(level.saturating_sub(offset).min(255)).unwrap_or(255);
+    (channel(0), channel(255), channel(510))
+}
+
+/// The overlap counts indexed for region queries: for each threshold, a
+/// [`PointTree`] over just the points covered that often, so memory grows
+/// with the number of covered points rather than with the area they span.
+struct OverlapMap {
+    /// `at_least[t - 1]` holds the points covered `t` or more times.
+    at_least: Vec<PointTree>,
+    histogram: Vec<usize>,
+    hottest: Vec<(Point, usize)>,
+}
+
+impl OverlapMap {
+    fn new(line_segments: &[LineSegment], rasterization: Rasterization) -> Self {
+        let counts = overlap_counts(line_segments, rasterization, true);
+
+        let max_count = counts.values().max().copied().unwrap_or(0);
+        let mut histogram = vec![0; max_count + 1];
+        for &count in counts.values() {
+            histogram[count] += 1;
+        }
+
+        let at_least = (1..=max_count)
+            .map(|threshold| {
+                PointTree::new(
+                    counts
+                        .iter()
+                        .filter(|&(_, &count)| count >= threshold)
+                        .map(|(&point, _)| point)
+                        .collect(),
+                )
+            })
+            .collect();
+
+        let mut hottest: Vec<(Point, usize)> = counts.into_iter().collect();
+        hottest.sort_unstable_by_key(|&(point, count)| (Reverse(count), point.y, point.x));
+
+        Self {
+            at_least,
+            histogram,
+            hottest,
+        }
+    }
+
+    /// How many points inside `rect` are covered at least `threshold` times.
+    fn count_in(&self, rect: Rect, threshold: usize) -> usize {
+        match threshold {
+            0 => rect.width() * rect.height(),
+            t => self
+                .at_least
+                .get(t - 1)
+                .map_or(0, |points| points.count_in(rect)),
+        }
+    }
+
+    /// `histogram()[d]` is how many points are covered exactly `d` times.
+    /// Uncovered points are not counted, so the first entry is always 0.
+    fn histogram(&self) -> &[usize] {
+        &self.histogram
+    }
+
+    /// The `k` most overlapped points, ties broken in reading order.
+    fn hottest(&self, k: usize) -> &[(Point, usize)] {
+        &self.hottest[..k.min(self.hottest.len())]
+    }
+}
+
+/// A merge-sort tree over points sorted by x: every node keeps the sorted
+/// y coordinates of the points below it, so counting the points in a
+/// rectangle takes O(log² n) and the tree O(n log n) memory.
+struct PointTree {
+    xs: Vec<i64>,
+    /// Node `i` has children `2i` and `2i + 1`; the leaves start at
+    /// `xs.len().next_power_of_two()`.
+    nodes: Vec<Vec<i64>>,
+}
+
+impl PointTree {
+    fn new(mut points: Vec<Point>) -> Self {
+        points.sort_unstable_by_key(|point| (point.x, point.y));
+        let leaves = points.len().next_power_of_two();
+        let mut nodes = vec![vec![]; 2 * leaves];
+        for (i, point) in points.iter().enumerate() {
+            nodes[leaves + i].push(point.y);
+        }
+        for i in (1..leaves).rev() {
+            let mut ys = [nodes[2 * i].as_slice(), nodes[2 * i + 1].as_slice()].concat();
+            ys.sort_unstable();
+            nodes[i] = ys;
+        }
+        Self {
+            xs: points.iter().map(|point| point.x).collect(),
+            nodes,
+        }
+    }
+
+    fn count_in(&self, rect: Rect) -> usize {
+        let leaves = self.nodes.len() / 2;
+        let mut left = leaves + self.xs.partition_point(|&x| x < rect.min.x);
+        let mut right = leaves + self.xs.partition_point(|&x| x <= rect.max.x);
+        let in_rows = |ys: &[i64]| {
+            ys.partition_point(|&y| y <= rect.max.y) - ys.partition_point(|&y| y < rect.min.y)
+        };
+        let mut total = 0;
+        while left < right {
+            if left % 2 == 1 {
+                total += in_rows(&self.nodes[left]);
+                left += 1;
+            }
+            if right % 2 == 1 {
+                right -= 1;
+                total += in_rows(&self.nodes[right]);
+            }
+            left /= 2;
+            right /= 2;
+        }
+        total
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -44,9 +394,28 @@ struct LineSegment {
     b: Point,
 }
 
+/// How a segment is turned into grid points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rasterization {
+    /// Only the lattice points the segment passes through exactly.
+    Lattice,
+    /// Every column (or row, for steep segments) gets the nearest point.
+    Bresenham,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stepper {
+    Lattice(Point),
+    Bresenham {
+        delta: Point,
+        direction: Point,
+        error: i64,
+    },
+}
+
 struct LineSegmentIterator {
     end: Point,
-    step: Point,
+    stepper: Stepper,
     next_point: Option<Point>,
 }
 
@@ -58,7 +427,7 @@ impl Iterator for LineSegmentIterator {
             self.next_point = if point == self.end {
                 None
             } else {
-                Some(point + self.step)
+                Some(self.advance(point))
             };
             return Some(point);
         }
@@ -66,20 +435,37 @@ impl Iterator for LineSegmentIterator {
     }
 }
 
+impl LineSegmentIterator {
+    fn advance(&mut self, point: Point) -> Point {
+        match &mut self.stepper {
+            Stepper::Lattice(step) => point + *step,
+            Stepper::Bresenham {
+                delta,
+                direction,
+                error,
+            } => {
+                let mut next = point;
+                let doubled = 2 * *error;
+                if doubled >= delta.y {
+                    *error += delta.y;
+                    next.x += direction.x;
+                }
+                if doubled <= delta.x {
+                    *error += delta.x;
+                    next.y += direction.y;
+                }
+                next
+            }
+        }
+    }
+}
+
 impl IntoIterator for LineSegment {
     type Item = Point;
     type IntoIter = LineSegmentIterator;
 
     fn into_iter(self) -> Self::IntoIter {
-        let step = self.step();
-        let end = self.b;
-        let next_point = Some(self.a);
-
-        Self::IntoIter {
-            end,
-            step,
-            next_point,
-        }
+        self.rasterize(Rasterization::Lattice)
     }
 }
 
@@ -95,6 +481,30 @@ impl FromStr for LineSegment {
 }
 
 impl LineSegment {
+    fn rasterize(self, rasterization: Rasterization) -> LineSegmentIterator {
+        let delta = self.b - self.a;
+        let stepper = match rasterization {
+            Rasterization::Lattice => Stepper::Lattice(delta.reduced()),
+            Rasterization::Bresenham => {
+                let delta = Point {
+                    x: delta.x.abs(),
+                    y: -delta.y.abs(),
+                };
+                Stepper::Bresenham {
+                    delta,
+                    direction: self.step(),
+                    error: delta.x + delta.y,
+                }
+            }
+        };
+
+        LineSegmentIterator {
+            end: self.b,
+            stepper,
+            next_point: Some(self.a),
+        }
+    }
+
     fn step(&self) -> Point {
         let x = match self.a.x.cmp(&self.b.x) {
             Ordering::Less => 1,
@@ -122,9 +532,193 @@ impl LineSegment {
     }
 }
 
+/// The infinite line through a segment, as `a * x + b * y = c` with its
+/// reduced direction normalized to point right (or down, when vertical).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Line {
+    step: Point,
+    c: i64,
+}
+
+impl Line {
+    fn through(segment: &LineSegment) -> Self {
+        let step = match (segment.b - segment.a).reduced() {
+            Point { x: 0, y: 0 } => Point { x: 1, y: 0 },
+            step if step.x < 0 || (step.x == 0 && step.y < 0) => Point {
+                x: -step.x,
+                y: -step.y,
+            },
+            step => step,
+        };
+        let (a, b) = (step.y, -step.x);
+        Self {
+            step,
+            c: a * segment.a.x + b * segment.a.y,
+        }
+    }
+
+    /// Position of a lattice point of the line, counted in steps.
+    fn position(&self, point: Point) -> i64 {
+        if self.step.x == 0 {
+            point.y
+        } else {
+            point.x.div_euclid(self.step.x)
+        }
+    }
+
+    /// The lattice point where two lines cross, if there is one.
+    fn crossing(&self, other: &Self) -> Option<Point> {
+        let (a1, b1, c1) = (
+            i128::from(self.step.y),
+            -i128::from(self.step.x),
+            i128::from(self.c),
+        );
+        let (a2, b2, c2) = (
+            i128::from(other.step.y),
+            -i128::from(other.step.x),
+            i128::from(other.c),
+        );
+        let det = a1 * b2 - a2 * b1;
+        if det == 0 {
+            return None;
+        }
+        let x = c1 * b2 - c2 * b1;
+        let y = a1 * c2 - a2 * c1;
+        if x % det != 0 || y % det != 0 {
+            return None;
+        }
+        Some(Point {
+            x: i64::try_from(x / det).ok()?,
+            y: i64::try_from(y / det).ok()?,
+        })
+    }
+}
+
+/// A stretch of a line covered by the same number of segments.
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    line: Line,
+    start: i64,
+    end: i64,
+    coverage: usize,
+}
+
+impl Run {
+    fn len(&self) -> usize {
+        usize::try_from(self.end - self.start + 1).expect("empty run")
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        (self.start..=self.end).contains(&self.line.position(point))
+    }
+}
+
+/// Counts overlapping lattice points without visiting them. Collinear
+/// segments are merged into runs of constant coverage with a sweep along
+/// their shared line, and only the points where runs on different lines
+/// cross are looked at individually.
+///
+/// Only the runs are stored, so memory stays proportional to the number of
+/// segments. Crossings are found again for each count by testing every pair
+/// of runs, which takes time quadratic in the number of runs.
+struct OverlapCounter {
+    runs: Vec<Run>,
+}
+
+impl OverlapCounter {
+    fn new(line_segments: &[LineSegment]) -> Self {
+        let mut lines: HashMap<Line, Vec<(i64, isize)>> = HashMap::default();
+        for segment in line_segments {
+            let line = Line::through(segment);
+            let (a, b) = (line.position(segment.a), line.position(segment.b));
+            let events = lines.entry(line).or_default();
+            events.push((a.min(b), 1));
+            events.push((a.max(b) + 1, -1));
+        }
+
+        let mut runs = vec![];
+        for (line, mut events) in lines {
+            events.sort_unstable();
+            let mut coverage = 0isize;
+            for pair in events.windows(2) {
+                let (position, delta) = pair[0];
+                coverage += delta;
+                if coverage > 0 && pair[1].0 > position {
+                    runs.push(Run {
+                        line,
+                        start: position,
+                        end: pair[1].0 - 1,
+                        coverage: coverage.unsigned_abs(),
+                    });
+                }
+            }
+        }
+
+        Self { runs }
+    }
+
+    /// The points where other runs cross run `i`, each with the indices of
+    /// every run through it, `i` included. Only points where `i` is the
+    /// lowest index through them are returned, so across all runs each
+    /// crossing is reported exactly once.
+    fn crossings_owned_by(&self, i: usize) -> Vec<(Point, Vec<usize>)> {
+        let run = &self.runs[i];
+        let mut crossings: Vec<(Point, usize)> = self
+            .runs
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .filter_map(|(j, other)| {
+                let point = run.line.crossing(&other.line)?;
+                (run.contains(point) && other.contains(point)).then_some((point, j))
+            })
+            .collect();
+        crossings.sort_unstable_by_key(|&(point, j)| (point.x, point.y, j));
+
+        let mut owned = vec![];
+        for group in crossings.chunk_by(|a, b| a.0 == b.0) {
+            if group.iter().all(|&(_, j)| j > i) {
+                let mut through = vec![i];
+                through.extend(group.iter().map(|&(_, j)| j));
+                owned.push((group[0].0, through));
+            }
+        }
+        owned
+    }
+
+    /// How many points are covered by at least `threshold` segments.
+    fn count_at_least(&self, threshold: usize) -> usize {
+        let on_runs: usize = self
+            .runs
+            .iter()
+            .filter(|run| run.coverage >= threshold)
+            .map(Run::len)
+            .sum();
+        let mut counted_at_crossings = 0;
+        let mut crossings_at_threshold = 0;
+        for i in 0..self.runs.len() {
+            for (_, through) in self.crossings_owned_by(i) {
+                let coverages = through.iter().map(|&idx| self.runs[idx].coverage);
+                counted_at_crossings += coverages.clone().filter(|&c| c >= threshold).count();
+                if coverages.sum::<usize>() >= threshold {
+                    crossings_at_threshold += 1;
+                }
+            }
+        }
+        on_runs - counted_at_crossings + crossings_at_threshold
+    }
+}
+
 #[cfg(test)]
 mod line_segment_tests {
-    use super::{LineSegment, Point};
+    use super::{LineSegment, Point, Rasterization};
+
+    fn points(line: &str, rasterization: Rasterization) -> Vec<(i64, i64)> {
+        let line: LineSegment = line.parse().unwrap();
+        line.rasterize(rasterization)
+            .map(|point| (point.x, point.y))
+            .collect()
+    }
 
     #[test]
     fn test_parse() {
@@ -150,11 +744,60 @@ mod line_segment_tests {
         assert_eq!(Some(Point { x: 3, y: 2 }), points.next());
         assert_eq!(None, points.next());
     }
+
+    #[test]
+    fn test_modes_agree_on_straight_lines() {
+        for line in [
+            "5,2 -> 3,2",
+            "1,1 -> 1,4",
+            "8,0 -> 0,8",
+            "2,2 -> 5,5",
+            "3,3 -> 3,3",
+        ] {
+            assert_eq!(
+                points(line, Rasterization::Lattice),
+                points(line, Rasterization::Bresenham)
+            );
+        }
+        assert_eq!(
+            points("6,4 -> 2,0", Rasterization::Lattice),
+            vec![(6, 4), (5, 3), (4, 2), (3, 1), (2, 0)]
+        );
+    }
+
+    #[test]
+    fn test_lattice_arbitrary_slope() {
+        assert_eq!(
+            points("0,0 -> 4,2", Rasterization::Lattice),
+            vec![(0, 0), (2, 1), (4, 2)]
+        );
+        assert_eq!(
+            points("3,7 -> 0,-2", Rasterization::Lattice),
+            vec![(3, 7), (2, 4), (1, 1), (0, -2)]
+        );
+        assert_eq!(
+            points("0,0 -> 3,2", Rasterization::Lattice),
+            vec![(0, 0), (3, 2)]
+        );
+    }
+
+    #[test]
+    fn test_bresenham_arbitrary_slope() {
+        assert_eq!(
+            points("0,0 -> 4,2", Rasterization::Bresenham),
+            vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]
+        );
+        assert_eq!(
+            points("0,0 -> -1,-3", Rasterization::Bresenham),
+            vec![(0, 0), (0, -1), (-1, -2), (-1, -3)]
+        );
+    }
 }
 
 #[cfg(test)]
 mod day5_tests {
     use super::*;
+    use aoc2021::rng::Rng;
 
     const EXAMPLE_INPUT: [LineSegment; 10] = [
         LineSegment {
@@ -201,13 +844,246 @@ mod day5_tests {
 
     #[test]
     fn test_part1() {
-        let result = part1(&EXAMPLE_INPUT);
+        let result = part1(&EXAMPLE_INPUT, Rasterization::Lattice);
         assert_eq!(result, 5);
     }
 
     #[test]
     fn test_part2() {
-        let result = part2(&EXAMPLE_INPUT);
+        let result = part2(&EXAMPLE_INPUT, Rasterization::Lattice);
         assert_eq!(result, 12);
     }
+
+    fn hashed_counts(line_segments: &[LineSegment]) -> HashMap<Point, usize> {
+        let mut points: HashMap<Point, usize> = HashMap::default();
+        for line in line_segments {
+            for point in *line {
+                *points.entry(point).or_insert(0) += 1;
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn test_sweep_example() {
+        let straight: Vec<LineSegment> = EXAMPLE_INPUT
+            .iter()
+            .copied()
+            .filter(|line| !line.is_diagonal())
+            .collect();
+        assert_eq!(OverlapCounter::new(&straight).count_at_least(2), 5);
+        let counter = OverlapCounter::new(&EXAMPLE_INPUT);
+        let counts = hashed_counts(&EXAMPLE_INPUT);
+        for k in 1..=4 {
+            let expected = counts.values().filter(|&&c| c >= k).count();
+            assert_eq!(counter.count_at_least(k), expected);
+        }
+        assert_eq!(counter.count_at_least(2), 12);
+    }
+
+    #[test]
+    fn test_sweep_matches_hashing() {
+        let mut rng = Rng::new(5);
+        for _ in 0..20 {
+            let segments: Vec<LineSegment> = (0..30)
+                .map(|_| {
+                    let mut coordinate = || i64::try_from(rng.below(12)).unwrap();
+                    LineSegment {
+                        a: Point {
+                            x: coordinate(),
+                            y: coordinate(),
+                        },
+                        b: Point {
+                            x: coordinate(),
+                            y: coordinate(),
+                        },
+                    }
+                })
+                .collect();
+            let counter = OverlapCounter::new(&segments);
+            let counts = hashed_counts(&segments);
+            for k in 1..=5 {
+                let expected = counts.values().filter(|&&c| c >= k).count();
+                assert_eq!(counter.count_at_least(k), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sweep_huge_coordinates() {
+        let segments = [
+            "0,0 -> 2000000000,2000000000",
+            "0,2000000000 -> 2000000000,0",
+            "1000000000,0 -> 1000000000,3000000000",
+            "500,500 -> 1500,1500",
+        ]
+        .map(|line| line.parse::<LineSegment>().unwrap());
+        let counter = OverlapCounter::new(&segments);
+        assert_eq!(counter.count_at_least(3), 1);
+        assert_eq!(counter.count_at_least(2), 1002);
+        assert_eq!(counter.count_at_least(1), 7_000_000_001);
+    }
+
+    #[test]
+    fn test_render_ascii() {
+        let counts = overlap_counts(&EXAMPLE_INPUT, Rasterization::Lattice, true);
+        let expected = "\
+1.1....11.
+.111...2..
+..2.1.111.
+...1.2.2..
+.112313211
+...1.2....
+..1...1...
+.1.....1..
+1.......1.
+222111....
+";
+        assert_eq!(render_ascii(&counts, None), expected);
+        let window = Rect {
+            min: Point { x: 3, y: 3 },
+            max: Point { x: 5, y: 4 },
+        };
+        assert_eq!(render_ascii(&counts, Some(window)), "1.2\n231\n");
+    }
+
+    #[test]
+    fn test_render_part1_ascii() {
+        let counts = overlap_counts(&EXAMPLE_INPUT, Rasterization::Lattice, false);
+        let rendered = render_ascii(&counts, None);
+        assert_eq!(rendered.lines().next(), Some(".......1.."));
+        assert_eq!(rendered.lines().last(), Some("222111...."));
+
+        let window = Rect {
+            min: Point { x: 3, y: 3 },
+            max: Point { x: 5, y: 4 },
+        };
+        assert_eq!(image_options(&["straight"]), (false, None));
+        assert_eq!(
+            image_options(&["straight", "3,3", "5,4"]),
+            (false, Some(window))
+        );
+        assert_eq!(image_options(&["all"]), (true, None));
+        assert_eq!(image_options(&["3,3", "5,4"]), (true, Some(window)));
+    }
+
+    #[test]
+    fn test_render_pgm() {
+        let segments = ["0,0 -> 2,0", "1,0 -> 1,1"].map(|line| line.parse().unwrap());
+        let counts = overlap_counts(&segments, Rasterization::Lattice, true);
+        assert_eq!(
+            ImageFormat::Pgm.render(&counts, None),
+            "P2\n3 2\n2\n1 2 1\n0 1 0\n"
+        );
+        let window = Rect {
+            min: Point { x: 1, y: 1 },
+            max: Point { x: 2, y: 1 },
+        };
+        assert_eq!(
+            ImageFormat::Pgm.render(&counts, Some(window)),
+            "P2\n2 1\n1\n1 0\n"
+        );
+    }
+
+    #[test]
+    fn test_render_ppm() {
+        let segments = ["0,0 -> 1,0", "0,0 -> 0,0"].map(|line| line.parse().unwrap());
+        let counts = overlap_counts(&segments, Rasterization::Lattice, true);
+        assert_eq!(
+            ImageFormat::Ppm.render(&counts, None),
+            "P3\n2 1\n255\n255 255 255 255 127 0\n"
+        );
+    }
+
+    #[test]
+    fn test_netpbm_line_length() {
+        let segments = ["0,0 -> 99,0".parse().unwrap()];
+        let counts = overlap_counts(&segments, Rasterization::Lattice, true);
+        let image = ImageFormat::Ppm.render(&counts, None);
+        assert!(image.lines().all(|line| line.len() <= 70));
+        assert_eq!(image.split_whitespace().count(), 4 + 300);
+    }
+
+    #[test]
+    fn test_region_queries() {
+        let map = OverlapMap::new(&EXAMPLE_INPUT, Rasterization::Lattice);
+        let everything = Rect {
+            min: Point { x: -100, y: -100 },
+            max: Point { x: 100, y: 100 },
+        };
+        assert_eq!(map.count_in(everything, 2), 12);
+        assert_eq!(map.count_in(everything, 3), 2);
+        assert_eq!(map.count_in(everything, 4), 0);
+        let top_left = Rect {
+            min: Point { x: 0, y: 0 },
+            max: Point { x: 4, y: 4 },
+        };
+        assert_eq!(map.count_in(top_left, 2), 3);
+        assert_eq!(map.count_in(top_left, 1), 12);
+        let empty = Rect {
+            min: Point { x: 20, y: 20 },
+            max: Point { x: 30, y: 30 },
+        };
+        assert_eq!(map.count_in(empty, 1), 0);
+    }
+
+    #[test]
+    fn test_region_queries_match_counts() {
+        let counts = overlap_counts(&EXAMPLE_INPUT, Rasterization::Lattice, true);
+        let map = OverlapMap::new(&EXAMPLE_INPUT, Rasterization::Lattice);
+        for (x0, y0, x1, y1) in [(0, 0, 9, 9), (2, 3, 7, 5), (5, 0, 5, 9), (8, 8, 12, 12)] {
+            let rect = Rect {
+                min: Point { x: x0, y: y0 },
+                max: Point { x: x1, y: y1 },
+            };
+            for threshold in 1..=3 {
+                let expected = rect
+                    .rows()
+                    .flatten()
+                    .filter(|point| counts.get(point).is_some_and(|&c| c >= threshold))
+                    .count();
+                assert_eq!(map.count_in(rect, threshold), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_histogram_and_hottest() {
+        let map = OverlapMap::new(&EXAMPLE_INPUT, Rasterization::Lattice);
+        assert_eq!(map.histogram(), &[0, 27, 10, 2]);
+        assert_eq!(
+            map.hottest(3),
+            &[
+                (Point { x: 4, y: 4 }, 3),
+                (Point { x: 6, y: 4 }, 3),
+                (Point { x: 7, y: 1 }, 2)
+            ]
+        );
+        assert_eq!(map.hottest(100).len(), 39);
+    }
+
+    #[test]
+    fn test_region_queries_long_diagonals() {
+        let segments =
+            ["0,0 -> 20000,20000", "0,20000 -> 20000,0"].map(|line| line.parse().unwrap());
+        let map = OverlapMap::new(&segments, Rasterization::Lattice);
+        assert_eq!(map.histogram(), &[0, 40000, 1]);
+        let all = Rect {
+            min: Point { x: 0, y: 0 },
+            max: Point { x: 20000, y: 20000 },
+        };
+        assert_eq!(map.count_in(all, 1), 40001);
+        assert_eq!(map.count_in(all, 2), 1);
+        let corner = Rect {
+            min: Point { x: 0, y: 0 },
+            max: Point { x: 99, y: 99 },
+        };
+        assert_eq!(map.count_in(corner, 1), 100);
+    }
+
+    #[test]
+    fn test_bresenham_example() {
+        assert_eq!(part1(&EXAMPLE_INPUT, Rasterization::Bresenham), 5);
+        assert_eq!(part2(&EXAMPLE_INPUT, Rasterization::Bresenham), 12);
+    }
 }
@@ -1,23 +1,60 @@
 #![warn(clippy::all, clippy::pedantic)]
-use aoc2021::{input_lines, parse_lines};
-use std::collections::{HashMap, HashSet};
-use std::hash::Hash;
+use aoc2021::input_lines;
+use std::env;
+use std::fmt;
+use std::fs;
 use std::str::FromStr;
 
 fn main() {
-    let notes: Vec<NoteEntry> = parse_lines(input_lines()).collect();
-    let result = part1(&notes);
-    println!("Part 1: {}", result);
-    let result = part2(&notes);
-    println!("Part 2: {}", result);
+    let mut display = DisplayDefinition::default();
+    let mut trace_format = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "trace" | "trace-csv" => trace_format = Some(arg),
+            path => {
+                display = fs::read_to_string(path)
+                    .expect("could not read display definition")
+                    .parse()
+                    .expect("invalid display definition");
+            }
+        }
+    }
+    let notes: Vec<NoteEntry> = input_lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| NoteEntry::parse(line.trim(), &display).expect("invalid note"))
+        .collect();
+
+    if let Some(format) = trace_format {
+        for (i, note) in notes.iter().enumerate() {
+            let (result, trace) = decode_with_trace(note, &display);
+            if format == "trace-csv" {
+                print!("{}", trace.to_csv());
+            } else {
+                println!("Entry {}:", i + 1);
+                print!("{trace}");
+                match result {
+                    Ok(output) => println!("output: {output}\n"),
+                    Err(e) => println!("{e}\n"),
+                }
+            }
+        }
+        return;
+    }
+
+    let result = part1(&notes, &display);
+    println!("Part 1: {result}");
+    match part2(&notes, &display) {
+        Ok(result) => println!("Part 2: {result}"),
+        Err(e) => println!("Part 2: {e}"),
+    }
 }
 
-fn part1(notes: &[NoteEntry]) -> usize {
+fn part1(notes: &[NoteEntry], display: &DisplayDefinition) -> usize {
     notes
         .iter()
         .flat_map(|note| note.output_value.iter())
-        .fold(0, |sum, signal| {
-            if signal.is_unique_length() {
+        .fold(0, |sum, &signal| {
+            if display.is_unique_length(signal) {
                 sum + 1
             } else {
                 sum
@@ -25,215 +62,652 @@ fn part1(notes: &[NoteEntry]) -> usize {
         })
 }
 
-fn part2(notes: &[NoteEntry]) -> usize {
-    notes.iter().map(decode).sum()
+/// Sums the decoded outputs. Outputs that are not numbers, as on displays
+/// with letter glyphs, are reported instead.
+fn part2(notes: &[NoteEntry], display: &DisplayDefinition) -> Result<usize, DecodeError> {
+    notes.iter().try_fold(0, |sum, note| {
+        let output = decode(note, display)?;
+        output
+            .parse::<usize>()
+            .map(|value| sum + value)
+            .map_err(|_| DecodeError::NotANumber(output))
+    })
+}
+
+fn decode(note: &NoteEntry, display: &DisplayDefinition) -> Result<String, DecodeError> {
+    decode_with_trace(note, display).0
 }
 
-fn decode(note: &NoteEntry) -> usize {
-    let mapping = encoding(&note.signal_patterns);
-    let digits = note
-        .output_value
+fn decode_with_trace(
+    note: &NoteEntry,
+    display: &DisplayDefinition,
+) -> (Result<String, DecodeError>, Trace) {
+    let observed: Vec<Pattern> = note
+        .signal_patterns
         .iter()
-        .map(|digit| {
-            mapping.iter().fold(digit.0.clone(), |s, (k, v)| {
-                s.replace(&k.to_string(), &v.to_string())
+        .chain(note.output_value.iter())
+        .copied()
+        .collect();
+    let mut trace = Trace::default();
+    let output = solve(&observed, display, &mut trace).map(|wiring| {
+        note.output_value
+            .iter()
+            .map(|&signal| {
+                display
+                    .glyph(wiring.translate(signal))
+                    .expect("solved wiring")
             })
-        })
-        .map(|s| Digit::from_str(&s).expect("oops"));
-    digits
-        .map(|d| d.0.to_string())
-        .collect::<String>()
-        .parse::<usize>()
-        .expect("oops again")
+            .collect()
+    });
+    (output, trace)
+}
+
+/// A set of wires (or segments) as a bitmask, the display's first segment
+/// being the lowest bit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct Pattern(u32);
+
+impl Pattern {
+    fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    fn contains(self, wire: usize) -> bool {
+        self.0 & (1 << wire) != 0
+    }
 }
 
-#[derive(Clone, Default)]
-struct Pattern {
-    segments: HashSet<char>,
+/// The segments of a display and the glyph each combination of lit
+/// segments shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DisplayDefinition {
+    segments: Vec<char>,
+    glyphs: Vec<(char, Pattern)>,
+}
+
+impl Default for DisplayDefinition {
+    /// The standard seven-segment digits.
+    fn default() -> Self {
+        "abcdefg
+         0 abcefg
+         1 cf
+         2 acdeg
+         3 acdfg
+         4 bcdf
+         5 abdfg
+         6 abdefg
+         7 acf
+         8 abcdefg
+         9 abcdfg"
+            .parse()
+            .expect("valid seven-segment definition")
+    }
 }
 
-impl FromStr for Pattern {
+/// The first line names the segments; every other line is a glyph
+/// followed by the segments it lights, e.g. `7 acf`.
+impl FromStr for DisplayDefinition {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let segments: HashSet<char> = s.chars().collect();
-        Ok(Pattern { segments })
+        let mut lines = s.lines().map(str::trim).filter(|line| !line.is_empty());
+        let segments: Vec<char> = lines.next().ok_or("no segments")?.chars().collect();
+        if segments.len() > u32::BITS as usize {
+            return Err(format!("at most {} segments are supported", u32::BITS));
+        }
+        if let Some(&dup) = segments
+            .iter()
+            .enumerate()
+            .find_map(|(i, c)| segments[..i].contains(c).then_some(c))
+        {
+            return Err(format!("segment {dup} is named twice"));
+        }
+
+        let mut display = Self {
+            segments,
+            glyphs: vec![],
+        };
+        for line in lines {
+            let (glyph, lit) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("expected a glyph and its segments: {line}"))?;
+            let mut glyph = glyph.chars();
+            let (Some(glyph), None) = (glyph.next(), glyph.next()) else {
+                return Err(format!("glyphs must be one character: {line}"));
+            };
+            let lit = display.parse_pattern(lit.trim())?;
+            if let Some(&(other, _)) = display
+                .glyphs
+                .iter()
+                .find(|&&(g, p)| g == glyph || p == lit)
+            {
+                return Err(format!("glyph {glyph} clashes with {other}"));
+            }
+            display.glyphs.push((glyph, lit));
+        }
+        if display.glyphs.is_empty() {
+            return Err("no glyphs".to_string());
+        }
+        Ok(display)
     }
 }
 
-impl Pattern {
-    fn len(&self) -> usize {
-        self.segments.len()
+impl DisplayDefinition {
+    fn all_segments(&self) -> u32 {
+        u32::MAX >> (u32::BITS as usize - self.segments.len())
+    }
+
+    fn segment_name(&self, segment: usize) -> char {
+        self.segments[segment]
     }
 
-    fn is_one(&self) -> bool {
-        self.len() == 2
+    fn parse_pattern(&self, s: &str) -> Result<Pattern, String> {
+        s.chars().try_fold(Pattern(0), |pattern, c| {
+            match self.segments.iter().position(|&segment| segment == c) {
+                Some(wire) => Ok(Pattern(pattern.0 | 1 << wire)),
+                None => Err(format!("'{c}' is not a wire in {s}")),
+            }
+        })
+    }
+
+    fn format_pattern(&self, pattern: Pattern) -> String {
+        (0..self.segments.len())
+            .filter(|&wire| pattern.contains(wire))
+            .map(|wire| self.segment_name(wire))
+            .collect()
     }
 
-    fn is_seven(&self) -> bool {
-        self.len() == 3
+    /// The glyphs a pattern could show, going by how many segments it lights.
+    fn possible_glyphs(&self, pattern: Pattern) -> impl Iterator<Item = &(char, Pattern)> {
+        self.glyphs
+            .iter()
+            .filter(move |(_, lit)| lit.len() == pattern.len())
     }
 
-    fn is_four(&self) -> bool {
-        self.len() == 4
+    fn is_unique_length(&self, pattern: Pattern) -> bool {
+        self.possible_glyphs(pattern).count() == 1
     }
 
-    fn is_eight(&self) -> bool {
-        self.len() == 7
+    fn glyph(&self, segments: Pattern) -> Option<char> {
+        self.glyphs
+            .iter()
+            .find(|&&(_, lit)| lit == segments)
+            .map(|&(glyph, _)| glyph)
     }
 }
 
-fn difference<T>(a: &HashSet<T>, b: &HashSet<T>) -> HashSet<T>
-where
-    T: Eq + Hash + Clone,
-{
-    a.difference(b).cloned().collect()
+/// Which segment each wire drives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Wiring {
+    names: Vec<char>,
+    segments: Vec<usize>,
 }
 
-fn intersection<T>(a: &HashSet<T>, b: &HashSet<T>) -> HashSet<T>
-where
-    T: Eq + Hash + Clone,
-{
-    a.intersection(b).cloned().collect()
+impl Wiring {
+    fn translate(&self, pattern: Pattern) -> Pattern {
+        (0..self.segments.len())
+            .filter(|&wire| pattern.contains(wire))
+            .fold(Pattern(0), |lit, wire| {
+                Pattern(lit.0 | 1 << self.segments[wire])
+            })
+    }
 }
 
-fn union<T>(a: &HashSet<T>, b: &HashSet<T>) -> HashSet<T>
-where
-    T: Eq + Hash + Clone,
-{
-    a.union(b).cloned().collect()
+impl fmt::Display for Wiring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (wire, &segment) in self.segments.iter().enumerate() {
+            if wire > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}→{}", self.names[wire], self.names[segment])?;
+        }
+        Ok(())
+    }
 }
 
-#[allow(clippy::similar_names, clippy::many_single_char_names)]
-fn encoding(patterns: &[Pattern]) -> HashMap<char, char> {
-    let seven = patterns
-        .iter()
-        .find(|&p| p.is_seven())
-        .expect("could not find 7");
-    let one = patterns
-        .iter()
-        .find(|&p| p.is_one())
-        .expect("could not find 1");
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DecodeError {
+    /// No wiring maps every pattern onto a glyph.
+    Unsolvable(String),
+    /// More than one wiring maps every pattern onto a glyph. Carries two of
+    /// them and the wires the deductions left open.
+    Ambiguous {
+        examples: [Wiring; 2],
+        wires: Vec<char>,
+    },
+    /// The output decoded to glyphs that do not form a number.
+    NotANumber(String),
+}
 
-    let a: HashSet<char> = difference(&seven.segments, &one.segments);
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Unsolvable(reason) => write!(f, "unsolvable: {reason}"),
+            DecodeError::Ambiguous { examples, wires } => {
+                let wires: String = wires.iter().collect();
+                write!(f, "ambiguous: wires {wires} fit more than one way, e.g.")?;
+                for wiring in examples {
+                    write!(f, "\n  {wiring}")?;
+                }
+                Ok(())
+            }
+            DecodeError::NotANumber(output) => write!(f, "{output} is not a number"),
+        }
+    }
+}
+
+/// Finds the wiring under which every observed pattern is a glyph,
+/// recording each deduction in `trace`.
+///
+/// Each pattern narrows the segments its wires can drive to those lit in
+/// some glyph of the same length, and the segments its dark wires can drive
+/// to those unlit in one. Wires left with a single option rule it out for
+/// every other wire. The remaining permutations are searched, abandoning a
+/// partial wiring as soon as some pattern can no longer become a glyph.
+fn solve(
+    patterns: &[Pattern],
+    display: &DisplayDefinition,
+    trace: &mut Trace,
+) -> Result<Wiring, DecodeError> {
+    let wires = display.segments.len();
+    let mut candidates = vec![display.all_segments(); wires];
+    let mut unique_patterns: Vec<Pattern> = vec![];
+    for &pattern in patterns {
+        if !unique_patterns.contains(&pattern) {
+            unique_patterns.push(pattern);
+        }
+    }
+    // The most telling patterns first, so the trace reads like a person
+    // working it out by hand.
+    unique_patterns
+        .sort_by_key(|&pattern| (display.possible_glyphs(pattern).count(), pattern.len()));
+    for &pattern in &unique_patterns {
+        narrow_by_pattern(pattern, display, &mut candidates, trace)?;
+    }
+    eliminate_taken_segments(display, &mut candidates, trace);
+    if let Some(wire) = (0..wires).find(|&wire| candidates[wire] == 0) {
+        return Err(DecodeError::Unsolvable(format!(
+            "no segment is left for wire {}",
+            display.segment_name(wire)
+        )));
+    }
 
-    let mut zero_six_nine = patterns.iter().filter(|&p| p.len() == 6);
-    let one_of_069 = zero_six_nine.next().expect("069");
-    let zero_six_nine_segments = zero_six_nine.fold(one_of_069.segments.clone(), |acc, zsn| {
-        intersection(&acc, &zsn.segments)
+    let mut search = Search {
+        display,
+        patterns: &unique_patterns,
+        candidates: &candidates,
+        assigned: vec![0; wires],
+        solutions: vec![],
+    };
+    search.assign(0, 0);
+    let open: Vec<char> = (0..wires)
+        .filter(|&wire| candidates[wire].count_ones() > 1)
+        .map(|wire| display.segment_name(wire))
+        .collect();
+    if !open.is_empty() {
+        trace.steps.push(Step::Searched {
+            wires: open.clone(),
+            wirings: search.solutions.len(),
+        });
+    }
+    let mut solutions = search.solutions.into_iter().map(|segments| Wiring {
+        names: display.segments.clone(),
+        segments,
     });
+    match (solutions.next(), solutions.next()) {
+        (None, _) => Err(DecodeError::Unsolvable(
+            "no wiring maps every pattern onto a glyph".to_string(),
+        )),
+        (Some(wiring), None) => {
+            trace.wiring = Some(wiring.clone());
+            Ok(wiring)
+        }
+        (Some(first), Some(second)) => Err(DecodeError::Ambiguous {
+            examples: [first, second],
+            wires: open,
+        }),
+    }
+}
 
-    let f = intersection(&zero_six_nine_segments, &one.segments);
-    let c = difference(&one.segments, &f);
+fn narrow_by_pattern(
+    pattern: Pattern,
+    display: &DisplayDefinition,
+    candidates: &mut [u32],
+    trace: &mut Trace,
+) -> Result<(), DecodeError> {
+    let glyphs: Vec<&(char, Pattern)> = display.possible_glyphs(pattern).collect();
+    if glyphs.is_empty() {
+        return Err(DecodeError::Unsolvable(format!(
+            "no glyph has {} segments, like {}",
+            pattern.len(),
+            display.format_pattern(pattern)
+        )));
+    }
+    let names: Vec<String> = glyphs.iter().map(|(glyph, _)| glyph.to_string()).collect();
+    trace.steps.push(Step::Candidates {
+        pattern: display.format_pattern(pattern),
+        glyphs: names.join(" "),
+    });
 
-    let mut two_three_five = patterns.iter().filter(|&p| p.len() == 5);
-    let one_of_235 = two_three_five.next().expect("235");
-    let two_three_five_segments = two_three_five.fold(one_of_235.segments.clone(), |acc, ttf| {
-        intersection(&acc, &ttf.segments)
+    let all_segments = display.all_segments();
+    let (lit, unlit) = glyphs.iter().fold((0, 0), |(lit, unlit), (_, glyph)| {
+        (lit | glyph.0, unlit | (!glyph.0 & all_segments))
     });
-    let d_or_g = difference(&two_three_five_segments, &a);
-    let four = patterns.iter().find(|&p| p.is_four()).expect("4");
-    let d = intersection(&d_or_g, &four.segments);
-    let g = difference(&d_or_g, &four.segments);
-
-    let cd = union(&c, &d);
-    let cdf = union(&cd, &f);
-    let b = difference(&four.segments, &cdf);
-
-    let acdf = union(&cdf, &a);
-    let abcdf = union(&acdf, &b);
-    let abcdfg = union(&abcdf, &g);
-    let eight = patterns.iter().find(|&p| p.is_eight()).expect("8");
-    let e = difference(&eight.segments, &abcdfg);
-
-    let keys = [a, b, c, d, e, f, g].into_iter().flatten();
-    let values = ['A', 'B', 'C', 'D', 'E', 'F', 'G'];
-    keys.zip(values.into_iter()).collect()
+    let (lit_term, unlit_term) = match &names[..] {
+        [glyph] => (glyph.clone(), format!("¬{glyph}")),
+        _ => (
+            format!("({})", names.join("∪")),
+            format!("¬({})", names.join("∩")),
+        ),
+    };
+    for (wire, options) in candidates.iter_mut().enumerate() {
+        let (allowed, term) = if pattern.contains(wire) {
+            (lit, &lit_term)
+        } else {
+            (unlit, &unlit_term)
+        };
+        if *options & !allowed != 0 {
+            *options &= allowed;
+            trace.steps.push(Step::Narrowed {
+                wire: display.segment_name(wire),
+                term: term.clone(),
+                segments: display.format_pattern(Pattern(*options)),
+            });
+        }
+    }
+    Ok(())
 }
 
-#[derive(Default, Clone)]
-struct Signal(String);
+/// Rules a segment out for every other wire once some wire must drive it.
+fn eliminate_taken_segments(
+    display: &DisplayDefinition,
+    candidates: &mut [u32],
+    trace: &mut Trace,
+) {
+    let wires = candidates.len();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for wire in 0..wires {
+            if candidates[wire].count_ones() != 1 {
+                continue;
+            }
+            for other in (0..wires).filter(|&other| other != wire) {
+                if candidates[other] & candidates[wire] != 0 {
+                    candidates[other] &= !candidates[wire];
+                    changed = true;
+                    trace.steps.push(Step::Eliminated {
+                        wire: display.segment_name(other),
+                        segment: display.format_pattern(Pattern(candidates[wire])),
+                        by: display.segment_name(wire),
+                        segments: display.format_pattern(Pattern(candidates[other])),
+                    });
+                }
+            }
+        }
+    }
+}
 
-impl FromStr for Signal {
-    type Err = String;
+/// One deduction made while solving a wiring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    /// How many segments a pattern lights limits which glyphs it can be.
+    Candidates { pattern: String, glyphs: String },
+    /// Being lit (or dark) in a pattern narrowed what a wire can drive.
+    Narrowed {
+        wire: char,
+        term: String,
+        segments: String,
+    },
+    /// Another wire must drive `segment`, so this one cannot.
+    Eliminated {
+        wire: char,
+        segment: String,
+        by: char,
+        segments: String,
+    },
+    /// The wires still left with several options were searched for
+    /// consistent wirings, stopping at [`SEARCH_LIMIT`].
+    Searched { wires: Vec<char>, wirings: usize },
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(s.to_string()))
-    }
+/// The deductions behind a wiring, in the order they were made. Glyphs
+/// stand for the segments they light, so `d ∈ 7 ∩ ¬1` reads "wire d
+/// drives a segment lit in 7 but not in 1".
+#[derive(Debug, Clone, Default)]
+struct Trace {
+    steps: Vec<Step>,
+    wiring: Option<Wiring>,
 }
 
-impl Signal {
-    fn is_unique_length(&self) -> bool {
-        self.possibile_digits().len() == 1
+impl Trace {
+    /// How each wire's segment was pinned down, e.g. `d → a = 7 ∩ ¬1`.
+    fn derivations(&self) -> Vec<(char, char, String)> {
+        let Some(wiring) = &self.wiring else {
+            return vec![];
+        };
+        wiring
+            .names
+            .iter()
+            .zip(&wiring.segments)
+            .map(|(&wire, &segment)| {
+                let mut terms: Vec<String> = vec![];
+                let mut removed: Vec<String> = vec![];
+                for step in &self.steps {
+                    match step {
+                        Step::Narrowed { wire: w, term, .. } if *w == wire => {
+                            terms.push(term.clone());
+                        }
+                        Step::Eliminated {
+                            wire: w, segment, ..
+                        } if *w == wire => removed.push(segment.clone()),
+                        _ => {}
+                    }
+                }
+                let mut formula = terms.join(" ∩ ");
+                for segment in removed {
+                    formula.push_str(" − ");
+                    formula.push_str(&segment);
+                }
+                if self.steps.iter().any(
+                    |step| matches!(step, Step::Searched { wires, .. } if wires.contains(&wire)),
+                ) {
+                    formula.push_str(" (by search)");
+                }
+                (wire, wiring.names[segment], formula)
+            })
+            .collect()
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = "step,kind,subject,via,result\n".to_string();
+        for (i, step) in self.steps.iter().enumerate() {
+            let row = match step {
+                Step::Candidates { pattern, glyphs } => {
+                    ["candidates", pattern, "", glyphs].map(str::to_string)
+                }
+                Step::Narrowed {
+                    wire,
+                    term,
+                    segments,
+                } => [
+                    "narrowed".to_string(),
+                    wire.to_string(),
+                    term.clone(),
+                    segments.clone(),
+                ],
+                Step::Eliminated {
+                    wire, by, segments, ..
+                } => [
+                    "eliminated".to_string(),
+                    wire.to_string(),
+                    by.to_string(),
+                    segments.clone(),
+                ],
+                Step::Searched { wires, wirings } => [
+                    "searched".to_string(),
+                    wires.iter().collect(),
+                    String::new(),
+                    wiring_count(*wirings),
+                ],
+            };
+            csv.push_str(&i.to_string());
+            csv.push(',');
+            csv.push_str(&csv_row(&row));
+            csv.push('\n');
+        }
+        for (wire, segment, formula) in self.derivations() {
+            let row = [
+                "wire".to_string(),
+                wire.to_string(),
+                formula,
+                segment.to_string(),
+            ];
+            csv.push(',');
+            csv.push_str(&csv_row(&row));
+            csv.push('\n');
+        }
+        csv
     }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
 
-    fn possibile_digits(&self) -> Vec<u8> {
-        match self.0.len() {
-            2 => vec![1],
-            3 => vec![7],
-            4 => vec![4],
-            5 => vec![2, 3, 5],
-            6 => vec![0, 6, 9],
-            7 => vec![8],
-            _ => vec![],
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            match step {
+                Step::Candidates { pattern, glyphs } => writeln!(f, "{pattern} could be {glyphs}")?,
+                Step::Narrowed {
+                    wire,
+                    term,
+                    segments,
+                } => writeln!(f, "{wire} ∈ {term}, so {wire} ∈ {{{segments}}}")?,
+                Step::Eliminated {
+                    wire,
+                    segment,
+                    by,
+                    segments,
+                } => writeln!(f, "{by} = {segment}, so {wire} ∈ {{{segments}}}")?,
+                Step::Searched { wires, wirings } => {
+                    let wires: String = wires.iter().collect();
+                    let wirings = wiring_count(*wirings);
+                    writeln!(f, "searching {wires} leaves {wirings} wiring(s)")?;
+                }
+            }
+        }
+        if let Some(wiring) = &self.wiring {
+            writeln!(f, "wire map: {wiring}")?;
         }
+        for (wire, segment, formula) in self.derivations() {
+            writeln!(f, "{wire} → {segment} = {formula}")?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy)]
-struct Digit(u8);
+/// The search stops once it has found this many wirings, since a second one
+/// already makes the note ambiguous.
+const SEARCH_LIMIT: usize = 2;
 
-impl FromStr for Digit {
-    type Err = String;
+/// A search result count, with `+` once it reached [`SEARCH_LIMIT`].
+fn wiring_count(wirings: usize) -> String {
+    if wirings < SEARCH_LIMIT {
+        wirings.to_string()
+    } else {
+        format!("{wirings}+")
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut chars: Vec<char> = s.chars().collect();
-        chars.sort_unstable();
-        let segments: String = chars.iter().collect();
-        match segments.as_str() {
-            "ABCEFG" => Ok(Digit(0)),
-            "CF" => Ok(Digit(1)),
-            "ACDEG" => Ok(Digit(2)),
-            "ACDFG" => Ok(Digit(3)),
-            "BCDF" => Ok(Digit(4)),
-            "ABDFG" => Ok(Digit(5)),
-            "ABDEFG" => Ok(Digit(6)),
-            "ACF" => Ok(Digit(7)),
-            "ABCDEFG" => Ok(Digit(8)),
-            "ABCDFG" => Ok(Digit(9)),
-            _ => Err(segments),
+struct Search<'a> {
+    display: &'a DisplayDefinition,
+    patterns: &'a [Pattern],
+    candidates: &'a [u32],
+    assigned: Vec<usize>,
+    solutions: Vec<Vec<usize>>,
+}
+
+impl Search<'_> {
+    fn assign(&mut self, wire: usize, used: u32) {
+        if self.solutions.len() == SEARCH_LIMIT {
+            return;
         }
+        if wire == self.assigned.len() {
+            self.solutions.push(self.assigned.clone());
+            return;
+        }
+        for segment in 0..self.assigned.len() {
+            if self.candidates[wire] & !used & (1 << segment) == 0 {
+                continue;
+            }
+            self.assigned[wire] = segment;
+            if self.is_consistent(wire + 1) {
+                self.assign(wire + 1, used | 1 << segment);
+            }
+        }
+    }
+
+    /// Whether every pattern could still show a glyph, given the segments
+    /// of the first `wires` wires.
+    fn is_consistent(&self, wires: usize) -> bool {
+        self.patterns.iter().all(|&pattern| {
+            let (mut lit, mut dark) = (0, 0);
+            for (wire, &segment) in self.assigned[..wires].iter().enumerate() {
+                if pattern.contains(wire) {
+                    lit |= 1 << segment;
+                } else {
+                    dark |= 1 << segment;
+                }
+            }
+            self.display
+                .possible_glyphs(pattern)
+                .any(|(_, glyph)| glyph.0 & lit == lit && glyph.0 & dark == 0)
+        })
     }
 }
 
 #[derive(Default, Clone)]
 struct NoteEntry {
-    signal_patterns: [Pattern; 10],
-    output_value: [Signal; 4],
+    signal_patterns: Vec<Pattern>,
+    output_value: Vec<Pattern>,
 }
 
 impl FromStr for NoteEntry {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut entry = Self::default();
-        let parts: Vec<&str> = s.split(" | ").collect();
-
-        let signal_patterns = parts[0].split(' ');
-        for (i, signal) in signal_patterns.enumerate() {
-            if let Ok(signal) = Pattern::from_str(signal) {
-                entry.signal_patterns[i] = signal;
-            }
-        }
-
-        let output_value = parts[1].split(' ');
-        for (i, signal) in output_value.enumerate() {
-            if let Ok(signal) = Signal::from_str(signal) {
-                entry.output_value[i] = signal;
-            }
-        }
+        Self::parse(s, &DisplayDefinition::default())
+    }
+}
 
-        Ok(entry)
+impl NoteEntry {
+    fn parse(s: &str, display: &DisplayDefinition) -> Result<Self, String> {
+        let (signal_patterns, output_value) = s
+            .split_once(" | ")
+            .ok_or_else(|| format!("missing separator in {s}"))?;
+        let parse = |patterns: &str| {
+            patterns
+                .split_whitespace()
+                .map(|pattern| display.parse_pattern(pattern))
+                .collect::<Result<Vec<Pattern>, String>>()
+        };
+
+        Ok(Self {
+            signal_patterns: parse(signal_patterns)?,
+            output_value: parse(output_value)?,
+        })
     }
 }
 
@@ -267,28 +741,242 @@ mod day8_tests {
     #[test]
     fn test_part1_short() {
         let input = parse(&SHORT_EXAMPLE);
-        let result = part1(&input);
+        let result = part1(&input, &DisplayDefinition::default());
         assert_eq!(result, 0);
     }
 
     #[test]
     fn test_part1_long() {
         let input = parse(&LONG_EXAMPLE);
-        let result = part1(&input);
+        let result = part1(&input, &DisplayDefinition::default());
         assert_eq!(result, 26);
     }
 
     #[test]
     fn test_part2_short() {
         let input = parse(&SHORT_EXAMPLE);
-        let result = part2(&input);
-        assert_eq!(result, 5353);
+        let result = part2(&input, &DisplayDefinition::default());
+        assert_eq!(result, Ok(5353));
     }
 
     #[test]
     fn test_part2_long() {
         let input = parse(&LONG_EXAMPLE);
-        let result = part2(&input);
-        assert_eq!(result, 61229);
+        let result = part2(&input, &DisplayDefinition::default());
+        assert_eq!(result, Ok(61229));
+    }
+
+    #[test]
+    fn test_solve_wiring() {
+        let input = parse(&SHORT_EXAMPLE);
+        let wiring = solve(
+            &input[0].signal_patterns,
+            &DisplayDefinition::default(),
+            &mut Trace::default(),
+        )
+        .unwrap();
+        assert_eq!(wiring.to_string(), "a→c b→f c→g d→a e→b f→d g→e");
+    }
+
+    #[test]
+    fn test_trace_derivations() {
+        let input = parse(&SHORT_EXAMPLE);
+        let (output, trace) = decode_with_trace(&input[0], &DisplayDefinition::default());
+        assert_eq!(output, Ok("5353".to_string()));
+        let derivations: Vec<String> = trace
+            .derivations()
+            .into_iter()
+            .map(|(wire, segment, formula)| format!("{wire} → {segment} = {formula}"))
+            .collect();
+        assert_eq!(
+            derivations,
+            vec![
+                "a → c = 1 ∩ ¬(0∩6∩9)",
+                "b → f = 1 − c",
+                "c → g = ¬1 ∩ ¬7 ∩ ¬4 − e",
+                "d → a = ¬1 ∩ 7",
+                "e → b = ¬1 ∩ ¬7 ∩ 4 ∩ ¬(2∩3∩5)",
+                "f → d = ¬1 ∩ ¬7 ∩ 4 ∩ ¬(0∩6∩9)",
+                "g → e = ¬1 ∩ ¬7 ∩ ¬4 ∩ ¬(2∩3∩5)",
+            ]
+        );
+        let explanation = trace.to_string();
+        assert!(explanation.contains("ab could be 1\n"));
+        assert!(explanation.contains("wire map: a→c b→f c→g d→a e→b f→d g→e\n"));
+    }
+
+    #[test]
+    fn test_trace_csv() {
+        let input = parse(&SHORT_EXAMPLE);
+        let (_, trace) = decode_with_trace(&input[0], &DisplayDefinition::default());
+        let csv = trace.to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("step,kind,subject,via,result"));
+        assert_eq!(lines.next(), Some("0,candidates,ab,,1"));
+        assert_eq!(lines.next(), Some("1,narrowed,a,1,cf"));
+        assert!(csv.contains(",wire,d,¬1 ∩ 7,a\n"));
+        assert_eq!(
+            csv_row(&["a,b".to_string(), "x\"y".to_string()]),
+            "\"a,b\",\"x\"\"y\""
+        );
+    }
+
+    #[test]
+    fn test_searched_derivations() {
+        let display: DisplayDefinition = "abcd\n0 abc\n1 ad\n2 bd\n3 bc".parse().unwrap();
+        let patterns: Vec<Pattern> = display.glyphs.iter().map(|&(_, pattern)| pattern).collect();
+        let mut trace = Trace::default();
+        solve(&patterns, &display, &mut trace).unwrap();
+        let derivations: Vec<String> = trace
+            .derivations()
+            .into_iter()
+            .map(|(wire, segment, formula)| format!("{wire} → {segment} = {formula}"))
+            .collect();
+        assert_eq!(
+            derivations,
+            vec![
+                "a → a = 0 (by search)",
+                "b → b = 0 (by search)",
+                "c → c = 0 (by search)",
+                "d → d = ¬0",
+            ]
+        );
+        assert!(trace
+            .to_string()
+            .contains("searching abc leaves 1 wiring(s)\n"));
+        assert!(trace.to_csv().contains(",searched,abc,,1\n"));
+    }
+
+    #[test]
+    fn test_unsolvable() {
+        let display = DisplayDefinition::default();
+        let patterns = ["a", "ab"].map(|p| display.parse_pattern(p).unwrap());
+        assert!(matches!(
+            solve(&patterns, &display, &mut Trace::default()),
+            Err(DecodeError::Unsolvable(_))
+        ));
+        let patterns = ["ab", "cd"].map(|p| display.parse_pattern(p).unwrap());
+        assert!(matches!(
+            solve(&patterns, &display, &mut Trace::default()),
+            Err(DecodeError::Unsolvable(_))
+        ));
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let display = DisplayDefinition::default();
+        let patterns = ["ab", "abc", "abde", "abcdefg"].map(|p| display.parse_pattern(p).unwrap());
+        let mut trace = Trace::default();
+        match solve(&patterns, &display, &mut trace) {
+            Err(DecodeError::Ambiguous { examples, wires }) => {
+                assert_ne!(examples[0], examples[1]);
+                assert_eq!(wires, vec!['a', 'b', 'd', 'e', 'f', 'g']);
+            }
+            other => panic!("expected ambiguity, got {other:?}"),
+        }
+        assert!(trace
+            .to_string()
+            .contains("searching abdefg leaves 2+ wiring(s)\n"));
+    }
+
+    #[test]
+    fn test_underdetermined_fourteen_segments() {
+        let display: DisplayDefinition = FOURTEEN_SEGMENT.parse().unwrap();
+        for line in ["abcdefgh abc | abc", "abc | abc"] {
+            let note = NoteEntry::parse(line, &display).unwrap();
+            let error = decode(&note, &display).unwrap_err();
+            assert!(matches!(error, DecodeError::Ambiguous { .. }));
+            assert_eq!(error.to_string().lines().count(), 3);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("ab cd | ef".parse::<NoteEntry>().is_ok());
+        assert!("ab cz | ef".parse::<NoteEntry>().is_err());
+        assert!("ab cd ef".parse::<NoteEntry>().is_err());
+    }
+
+    /// Rewires `text` as it would appear on a scrambled display, where
+    /// wire `i` drives segment `permutation[i]`.
+    fn scramble(display: &DisplayDefinition, permutation: &[usize], text: &str) -> String {
+        text.chars()
+            .map(|glyph| {
+                let (_, lit) = display.glyphs.iter().find(|(g, _)| *g == glyph).unwrap();
+                (0..permutation.len())
+                    .filter(|&wire| lit.contains(permutation[wire]))
+                    .map(|wire| display.segments[wire])
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    const FOURTEEN_SEGMENT: &str = "
+        abcdefghijklmn
+        0 abcdefjk
+        1 bcj
+        2 abdegh
+        3 abcdh
+        4 bcfgh
+        5 adfgm
+        6 acdefgh
+        7 abc
+        8 abcdefgh
+        9 abcdfgh
+        A abcefgh
+        E adefg
+        H bcefgh
+        I adil
+        K efgjm
+        M bcefij
+        N bcefim
+        X ijkm
+        Z adjk";
+
+    #[test]
+    fn test_fourteen_segment_display() {
+        let display: DisplayDefinition = FOURTEEN_SEGMENT.parse().unwrap();
+        let permutation = [7, 12, 0, 3, 9, 1, 13, 5, 10, 2, 4, 11, 8, 6];
+        let glyphs: String = display.glyphs.iter().map(|(g, _)| *g).collect();
+        let line = format!(
+            "{} | {}",
+            scramble(&display, &permutation, &glyphs),
+            scramble(&display, &permutation, "HEX42")
+        );
+        let note = NoteEntry::parse(&line, &display).unwrap();
+        assert_eq!(decode(&note, &display), Ok("HEX42".to_string()));
+        assert_eq!(
+            part2(&[note], &display),
+            Err(DecodeError::NotANumber("HEX42".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_custom_glyphs() {
+        let display: DisplayDefinition = "
+            pqrs
+            + pq
+            - q
+            = qr
+            * pqrs
+            / ps"
+            .parse()
+            .unwrap();
+        assert!(!display.is_unique_length(display.parse_pattern("qs").unwrap()));
+        assert!(display.is_unique_length(display.parse_pattern("s").unwrap()));
+        let note = NoteEntry::parse("rs s sp pqrs qr | sp s", &display).unwrap();
+        assert_eq!(decode(&note, &display), Ok("=-".to_string()));
+        assert_eq!(part1(&[note], &display), 1);
+    }
+
+    #[test]
+    fn test_invalid_definitions() {
+        assert!("abc".parse::<DisplayDefinition>().is_err());
+        assert!("aba\n1 a".parse::<DisplayDefinition>().is_err());
+        assert!("abc\n1 ab\n2 ab".parse::<DisplayDefinition>().is_err());
+        assert!("abc\n1 ab\n1 bc".parse::<DisplayDefinition>().is_err());
+        assert!("abc\n1 az".parse::<DisplayDefinition>().is_err());
+        assert!("abc\n12 ab".parse::<DisplayDefinition>().is_err());
     }
 }
@@ -1,14 +1,69 @@
 #![warn(clippy::all, clippy::pedantic)]
 use aoc2021::input_lines;
-use std::collections::{HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::env;
+use std::fmt;
 use std::ops::{Index, IndexMut};
+use std::str::FromStr;
 
 fn main() {
-    let height_map = HeightMap::from_lines(input_lines());
-    let result = part1(&height_map);
-    println!("Part 1: {}", result);
-    let result = part2(&height_map);
-    println!("Part 2: {}", result);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let height_map = match HeightMap::from_lines(input_lines()) {
+        Ok(height_map) => height_map,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    match args[..] {
+        ["water", ref options @ ..] => {
+            let water = configure(height_map, options).trapped_water();
+            println!("{water}");
+            println!("Total: {}", water.total);
+        }
+        ["report", ref options @ ..] => {
+            let height_map = configure(height_map, options);
+            for (i, basin) in height_map.basins().iter().enumerate() {
+                println!("{}: {basin}", basin_glyph(i));
+            }
+        }
+        [style @ ("basins" | "color"), ref options @ ..] => {
+            let height_map = configure(height_map, options);
+            let style = if style == "color" {
+                RenderStyle::Ansi
+            } else {
+                RenderStyle::Glyphs
+            };
+            print!("{}", height_map.render_basins(&height_map.basins(), style));
+        }
+        ref options => {
+            let height_map = configure(height_map, options);
+            let result = part1(&height_map);
+            println!("Part 1: {result}");
+            let result = part2(&height_map);
+            println!("Part 2: {result}");
+        }
+    }
+}
+
+/// Applies the optional `[connectivity [wall height]]` arguments.
+fn configure(height_map: HeightMap, options: &[&str]) -> HeightMap {
+    let (connectivity, wall_height) = match options {
+        [connectivity, wall_height] => (
+            connectivity.parse().expect("invalid connectivity"),
+            wall_height.parse().expect("wall height must be a number"),
+        ),
+        [connectivity] => (
+            connectivity.parse().expect("invalid connectivity"),
+            DEFAULT_WALL_HEIGHT,
+        ),
+        _ => (Connectivity::default(), DEFAULT_WALL_HEIGHT),
+    };
+    height_map
+        .with_connectivity(connectivity)
+        .with_wall_height(wall_height)
 }
 
 fn part1(height_map: &HeightMap) -> usize {
@@ -39,32 +94,116 @@ fn as_usize(i: isize) -> usize {
     usize::try_from(i).expect("lost sign")
 }
 
-#[derive(Default)]
+/// Heights at or above this never belong to a basin in the puzzle.
+const DEFAULT_WALL_HEIGHT: u32 = 9;
+
+/// Which cells count as adjacent when looking for low points and basins.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Connectivity {
+    /// Only the orthogonal neighbors.
+    #[default]
+    Four,
+    /// Orthogonal and diagonal neighbors.
+    Eight,
+}
+
+impl Connectivity {
+    fn offsets(self) -> &'static [Point] {
+        match self {
+            Connectivity::Four => &[(-1, 0), (0, -1), (1, 0), (0, 1)],
+            Connectivity::Eight => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        }
+    }
+}
+
+impl FromStr for Connectivity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" => Ok(Connectivity::Four),
+            "8" => Ok(Connectivity::Eight),
+            _ => Err(format!("connectivity must be 4 or 8, got {s:?}")),
+        }
+    }
+}
+
 struct HeightMap {
     locations: Vec<u32>,
     width: isize,
     height: isize,
+    connectivity: Connectivity,
+    wall_height: u32,
+}
+
+impl Default for HeightMap {
+    fn default() -> Self {
+        Self {
+            locations: Vec::new(),
+            width: 0,
+            height: 0,
+            connectivity: Connectivity::default(),
+            wall_height: DEFAULT_WALL_HEIGHT,
+        }
+    }
 }
 
 type Point = (isize, isize);
 impl HeightMap {
-    fn from_lines<I>(lines: I) -> Self
+    /// Reads one row of digits per line, skipping blank lines. Every row
+    /// must be as wide as the first.
+    fn from_lines<I>(lines: I) -> Result<Self, String>
     where
         I: Iterator<Item = String>,
     {
         let mut height_map = Self::default();
         for line in lines {
             let line = line.trim();
-            if height_map.width == 0 {
-                height_map.width = as_isize(line.len());
+            if line.is_empty() {
+                continue;
+            }
+            let width = as_isize(line.len());
+            if height_map.height == 0 {
+                height_map.width = width;
+            } else if width != height_map.width {
+                return Err(format!(
+                    "rows must all be {} cells wide, found one with {width}",
+                    height_map.width
+                ));
             }
             height_map.height += 1;
             for c in line.chars() {
-                let height = c.to_digit(10).expect("not a number");
+                let height = c
+                    .to_digit(10)
+                    .ok_or_else(|| format!("not a height: {c:?}"))?;
                 height_map.locations.push(height);
             }
         }
-        height_map
+        Ok(height_map)
+    }
+
+    fn with_connectivity(self, connectivity: Connectivity) -> Self {
+        Self {
+            connectivity,
+            ..self
+        }
+    }
+
+    /// Cells at or above `wall_height` separate basins.
+    fn with_wall_height(self, wall_height: u32) -> Self {
+        Self {
+            wall_height,
+            ..self
+        }
     }
 
     fn low_points(&self) -> Vec<Point> {
@@ -86,28 +225,136 @@ impl HeightMap {
     }
 
     fn basin_for(&self, point: Point) -> usize {
+        self.basin_cells(point).len()
+    }
+
+    /// Every cell below the wall height reachable from `point`.
+    fn basin_cells(&self, point: Point) -> HashSet<Point> {
         let mut queue = VecDeque::from([point]);
         let mut seen: HashSet<Point> = HashSet::default();
-        let mut size = 0;
+        let mut cells = HashSet::default();
         while let Some(p) = queue.pop_front() {
             if seen.contains(&p) {
                 continue;
             }
             seen.insert(p);
             let height = self[p];
-            if height < 9 {
-                size += 1;
+            if height < self.wall_height {
+                cells.insert(p);
                 queue.append(&mut VecDeque::from(self.neighboring_points(p)));
             }
         }
-        size
+        cells
+    }
+
+    /// Every basin on the map, in the order their first low point is found.
+    /// Low points that share a basin are reported together.
+    fn basins(&self) -> Vec<Basin> {
+        let mut basins: Vec<Basin> = vec![];
+        // A low point that is itself a wall has no basin.
+        let low_points = self
+            .low_points()
+            .into_iter()
+            .filter(|&low_point| self[low_point] < self.wall_height);
+        for low_point in low_points {
+            if let Some(basin) = basins.iter_mut().find(|b| b.cells.contains(&low_point)) {
+                basin.low_points.push(low_point);
+                continue;
+            }
+            let cells = self.basin_cells(low_point);
+            let heights = || cells.iter().map(|&pt| self[pt]);
+            let perimeter = cells
+                .iter()
+                .flat_map(|&(x, y)| [(x - 1, y), (x, y - 1), (x + 1, y), (x, y + 1)])
+                .filter(|pt| !cells.contains(pt))
+                .count();
+            basins.push(Basin {
+                low_points: vec![low_point],
+                min_height: heights().min().unwrap_or_default(),
+                max_height: heights().max().unwrap_or_default(),
+                top_left: (
+                    cells.iter().map(|&(x, _)| x).min().unwrap_or_default(),
+                    cells.iter().map(|&(_, y)| y).min().unwrap_or_default(),
+                ),
+                bottom_right: (
+                    cells.iter().map(|&(x, _)| x).max().unwrap_or_default(),
+                    cells.iter().map(|&(_, y)| y).max().unwrap_or_default(),
+                ),
+                perimeter,
+                cells,
+            });
+        }
+        basins
+    }
+
+    /// Draws the map with each basin's cells in its own glyph or colour.
+    /// Walls are `#` and cells outside every basin keep their height.
+    fn render_basins(&self, basins: &[Basin], style: RenderStyle) -> String {
+        let mut out = String::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let point = (x, y);
+                let height = self[point];
+                let basin = basins.iter().position(|b| b.cells.contains(&point));
+                match (basin, style) {
+                    _ if height >= self.wall_height => out.push('#'),
+                    (Some(i), RenderStyle::Glyphs) => out.push(basin_glyph(i)),
+                    (Some(i), RenderStyle::Ansi) => {
+                        let color = BASIN_COLORS[i % BASIN_COLORS.len()];
+                        out.push_str(&paint(height, color));
+                    }
+                    (None, _) => out.push(char::from_digit(height, 10).unwrap_or('?')),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// How deep the water stands on every cell after endless rain, with
+    /// anything that can reach the edge of the map draining away.
+    ///
+    /// Floods inwards from the border, always raising the lowest cell on the
+    /// frontier, so each cell fills up to the lowest spill level that
+    /// surrounds it.
+    fn trapped_water(&self) -> TrappedWater {
+        let mut depths = vec![0; self.locations.len()];
+        let mut seen = vec![false; self.locations.len()];
+        let mut frontier = BinaryHeap::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1 {
+                    let idx = self.index_for((x, y)).unwrap();
+                    seen[idx] = true;
+                    frontier.push(Reverse((self.locations[idx], (x, y))));
+                }
+            }
+        }
+        while let Some(Reverse((level, point))) = frontier.pop() {
+            for neighbor in self.neighboring_points(point) {
+                let idx = self.index_for(neighbor).unwrap();
+                if seen[idx] {
+                    continue;
+                }
+                seen[idx] = true;
+                let height = self.locations[idx];
+                depths[idx] = level.saturating_sub(height);
+                frontier.push(Reverse((level.max(height), neighbor)));
+            }
+        }
+        TrappedWater {
+            total: depths.iter().map(|&depth| u64::from(depth)).sum(),
+            depths,
+            width: as_usize(self.width),
+        }
     }
 
     fn neighboring_points(&self, (x, y): Point) -> Vec<Point> {
-        [(x - 1, y), (x, y - 1), (x + 1, y), (x, y + 1)]
+        self.connectivity
+            .offsets()
             .iter()
-            .filter(|&&pt| self.index_for(pt).is_ok())
-            .copied()
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .filter(|&pt| self.index_for(pt).is_ok())
             .collect()
     }
 
@@ -115,7 +362,7 @@ impl HeightMap {
         if self.width == 0 {
             return Err("width must me greater than 0".to_string());
         }
-        if x < 0 || x >= self.width || y < 0 || y >= self.width {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
             return Err("index out of bounds".to_string());
         }
         match (y * self.width) + x {
@@ -126,6 +373,93 @@ impl HeightMap {
     }
 }
 
+/// A connected region below the wall height, with the statistics the basin
+/// report prints.
+struct Basin {
+    low_points: Vec<Point>,
+    cells: HashSet<Point>,
+    min_height: u32,
+    max_height: u32,
+    top_left: Point,
+    bottom_right: Point,
+    /// Cell edges that border a wall, another basin or the edge of the map.
+    perimeter: usize,
+}
+
+impl Basin {
+    fn size(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+impl fmt::Display for Basin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lows: Vec<String> = self
+            .low_points
+            .iter()
+            .map(|(x, y)| format!("{x},{y}"))
+            .collect();
+        write!(
+            f,
+            "low {} size {} heights {}..={} box {},{}..{},{} perimeter {}",
+            lows.join(" "),
+            self.size(),
+            self.min_height,
+            self.max_height,
+            self.top_left.0,
+            self.top_left.1,
+            self.bottom_right.0,
+            self.bottom_right.1,
+            self.perimeter
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RenderStyle {
+    /// One letter per basin.
+    Glyphs,
+    /// Heights on a per-basin 256-colour background.
+    Ansi,
+}
+
+/// Background colours from the 256-colour palette, chosen to stay readable
+/// behind black digits.
+const BASIN_COLORS: [u8; 8] = [117, 150, 180, 183, 222, 152, 216, 229];
+
+fn paint(height: u32, color: u8) -> String {
+    format!("\x1b[30;48;5;{color}m{height}\x1b[0m")
+}
+
+fn basin_glyph(index: usize) -> char {
+    const GLYPHS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    char::from(GLYPHS[index % GLYPHS.len()])
+}
+
+/// Standing water left on a [`HeightMap`], laid out row by row like the map.
+struct TrappedWater {
+    total: u64,
+    depths: Vec<u32>,
+    width: usize,
+}
+
+impl fmt::Display for TrappedWater {
+    /// Draws the depth of each cell, with `.` for dry ground.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.depths.chunks(self.width.max(1)) {
+            let line: String = row
+                .iter()
+                .map(|&depth| match depth {
+                    0 => '.',
+                    depth => char::from_digit(depth.min(35), 36).unwrap(),
+                })
+                .collect();
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
 impl Index<Point> for HeightMap {
     type Output = u32;
 
@@ -155,20 +489,149 @@ mod day9_tests {
     ];
 
     fn string_input() -> impl Iterator<Item = String> {
-        EXAMPLE_INPUT.iter().map(|line| line.to_string())
+        EXAMPLE_INPUT.iter().map(ToString::to_string)
     }
 
     #[test]
     fn part1_example() {
-        let hm = HeightMap::from_lines(string_input());
+        let hm = HeightMap::from_lines(string_input()).unwrap();
         let result = part1(&hm);
         assert_eq!(result, 15);
     }
 
     #[test]
     fn part2_example() {
-        let hm = HeightMap::from_lines(string_input());
+        let hm = HeightMap::from_lines(string_input()).unwrap();
         let result = part2(&hm);
         assert_eq!(result, 1134);
     }
+
+    fn map_of(lines: &[&str]) -> HeightMap {
+        HeightMap::from_lines(lines.iter().map(ToString::to_string)).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rows() {
+        let hm = map_of(&["219", "398", "", "985", "\n"]);
+        assert_eq!((hm.width, hm.height), (3, 3));
+        assert_eq!(hm.low_points(), vec![(1, 0), (2, 2)]);
+
+        let ragged = ["219", "39", "98567"].iter().map(ToString::to_string);
+        assert_eq!(
+            HeightMap::from_lines(ragged).err(),
+            Some("rows must all be 3 cells wide, found one with 2".to_string())
+        );
+        let bad = ["21x"].iter().map(ToString::to_string);
+        assert!(HeightMap::from_lines(bad).is_err());
+    }
+
+    #[test]
+    fn test_tall_map() {
+        let hm = map_of(&["19", "99", "95", "94", "99", "21"]);
+        assert_eq!(hm.width, 2);
+        assert_eq!(hm.height, 6);
+        assert_eq!(hm[(1, 5)], 1);
+        assert_eq!(hm.low_points(), vec![(0, 0), (1, 3), (1, 5)]);
+        assert_eq!(part1(&hm), 2 + 5 + 2);
+        assert_eq!(hm.basin_for((1, 3)), 2);
+        assert_eq!(hm.basin_for((1, 5)), 2);
+        assert!(hm.index_for((0, 6)).is_err());
+        assert!(hm.index_for((2, 0)).is_err());
+    }
+
+    #[test]
+    fn test_wide_map() {
+        let hm = map_of(&["3219012", "4929123"]);
+        assert_eq!(hm.low_points(), vec![(2, 0), (4, 0)]);
+        assert_eq!(hm.basin_for((2, 0)), 5);
+        assert_eq!(hm.basin_for((4, 0)), 6);
+        assert_eq!(part2(&hm), 30);
+    }
+
+    #[test]
+    fn test_diagonal_connectivity() {
+        let lines = ["1999", "9299", "9939", "9990"];
+        let four = map_of(&lines);
+        assert_eq!(four.low_points(), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+        assert_eq!(four.basin_for((0, 0)), 1);
+        let eight = map_of(&lines).with_connectivity(Connectivity::Eight);
+        assert_eq!(eight.low_points(), vec![(0, 0), (3, 3)]);
+        assert_eq!(eight.basin_for((0, 0)), 4);
+        assert_eq!(eight.basin_for((3, 3)), 4);
+    }
+
+    #[test]
+    fn test_trapped_water() {
+        let hm = map_of(&["3333", "3113", "3333"]);
+        let water = hm.trapped_water();
+        assert_eq!(water.total, 4);
+        assert_eq!(water.to_string(), "....\n.22.\n....\n");
+
+        let leaky = map_of(&["3333", "3113", "3313"]).trapped_water();
+        assert_eq!(leaky.total, 0);
+
+        let tall = map_of(&["999", "919", "929", "959", "999"]).trapped_water();
+        assert_eq!(tall.total, 8 + 7 + 4);
+        assert_eq!(tall.to_string(), "...\n.8.\n.7.\n.4.\n...\n");
+    }
+
+    #[test]
+    fn test_trapped_water_connectivity() {
+        let lines = ["9999", "9199", "9919", "9990"];
+        assert_eq!(map_of(&lines).trapped_water().total, 16);
+        let eight = map_of(&lines).with_connectivity(Connectivity::Eight);
+        assert_eq!(eight.trapped_water().total, 0);
+    }
+
+    #[test]
+    fn test_basin_report() {
+        let hm = HeightMap::from_lines(string_input()).unwrap();
+        let basins = hm.basins();
+        let sizes: Vec<usize> = basins.iter().map(Basin::size).collect();
+        assert_eq!(sizes, vec![3, 9, 14, 9]);
+        assert_eq!(
+            basins[0].to_string(),
+            "low 1,0 size 3 heights 1..=3 box 0,0..1,1 perimeter 8"
+        );
+        assert_eq!(
+            basins[1].to_string(),
+            "low 9,0 size 9 heights 0..=4 box 5,0..9,2 perimeter 18"
+        );
+        assert_eq!(
+            hm.render_basins(&basins, RenderStyle::Glyphs),
+            "aa###bbbbb\na#ccc#b#bb\n#ccccc#d#b\nccccc#ddd#\n#c###ddddd\n"
+        );
+    }
+
+    #[test]
+    fn test_shared_basin() {
+        let hm = map_of(&["1219", "9999"]);
+        let basins = hm.basins();
+        assert_eq!(basins.len(), 1);
+        assert_eq!(basins[0].low_points, vec![(0, 0), (2, 0)]);
+        assert_eq!(basins[0].perimeter, 8);
+        let colored = hm.render_basins(&basins, RenderStyle::Ansi);
+        assert!(colored.starts_with("\x1b[30;48;5;117m1\x1b[0m"));
+        assert!(colored.ends_with("####\n"));
+    }
+
+    #[test]
+    fn test_wall_height() {
+        let hm = map_of(&["12345", "56789"]);
+        assert_eq!(hm.basin_for((0, 0)), 9);
+        let walled = map_of(&["12345", "56789"]).with_wall_height(5);
+        assert_eq!(walled.basin_for((0, 0)), 4);
+
+        let hm = map_of(&["959", "999", "919"]).with_wall_height(5);
+        let basins = hm.basins();
+        assert_eq!(basins.len(), 1);
+        assert_eq!(
+            basins[0].to_string(),
+            "low 1,2 size 1 heights 1..=1 box 1,2..1,2 perimeter 4"
+        );
+        assert_eq!(
+            Err("connectivity must be 4 or 8, got \"6\"".to_string()),
+            "6".parse::<Connectivity>()
+        );
+    }
 }
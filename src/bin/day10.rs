@@ -1,129 +1,655 @@
 #![warn(clippy::all, clippy::pedantic)]
 use aoc2021::input_lines;
 use aoc2021::stack::Stack;
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
     let input: Vec<String> = input_lines().map(|line| line.trim().to_string()).collect();
-    let result = part1(&input);
-    println!("Part 1: {}", result);
-    let result = part2(&input);
-    println!("Part 2: {}", result);
+    match args[..] {
+        ["check", ref options @ ..] => {
+            let grammar = grammar_from(options);
+            for line in &input {
+                if let Some(corruption) = parse_line(line, &grammar).corruption {
+                    println!("{}", diagnostic(line, &corruption));
+                }
+            }
+        }
+        ["tree", ref options @ ..] => {
+            let grammar = grammar_from(options);
+            for line in &input {
+                let chunks = parse_line_tree(line, &grammar).chunks.unwrap_or_default();
+                println!("{line}");
+                print!("{}", render_tree(&chunks));
+                println!(
+                    "max depth {}, chunks per depth {:?}",
+                    max_depth(&chunks),
+                    depth_histogram(&chunks)
+                );
+            }
+        }
+        ["repair", ref options @ ..] => {
+            let grammar = grammar_from(options);
+            for line in &input {
+                let repair = repair(line, &grammar);
+                if !repair.edits.is_empty() {
+                    let edits: Vec<String> = repair.edits.iter().map(ToString::to_string).collect();
+                    println!("{} ({})", repair.repaired, edits.join(", "));
+                }
+            }
+        }
+        _ => {
+            let grammar = Grammar::default();
+            let aggregate = args.first().map_or(Ok(Aggregate::default()), |a| a.parse());
+            let scheme =
+                ScoringScheme::default().with_aggregate(aggregate.expect("invalid aggregate"));
+            let result = part1(&input, &grammar, &scheme);
+            println!("Part 1: {result}");
+            match part2(&input, &grammar, &scheme) {
+                Ok(result) => println!("Part 2: {result}"),
+                Err(err) => println!("Part 2: {err}"),
+            }
+        }
+    }
 }
 
-fn part1(lines: &[String]) -> usize {
+/// Builds a grammar from the optional `[pairs [policy]]` arguments.
+fn grammar_from(options: &[&str]) -> Grammar {
+    match options {
+        [pairs, others] => Grammar::new(
+            &pairs.parse::<Pairs>().expect("invalid pairs").0,
+            others.parse().expect("invalid policy"),
+        ),
+        [pairs] => Grammar::new(
+            &pairs.parse::<Pairs>().expect("invalid pairs").0,
+            OtherChars::default(),
+        ),
+        _ => Ok(Grammar::default()),
+    }
+    .expect("invalid grammar")
+}
+
+fn part1(lines: &[String], grammar: &Grammar, scheme: &ScoringScheme) -> usize {
     lines
         .iter()
-        .map(|line| parse_line(line))
-        .map(|result| result.syntax_error_score())
+        .map(|line| parse_line(line, grammar))
+        .map(|result| result.syntax_error_score(scheme))
         .sum()
 }
 
-fn part2(lines: &[String]) -> usize {
-    let mut scores: Vec<usize> = lines
+fn part2(lines: &[String], grammar: &Grammar, scheme: &ScoringScheme) -> Result<usize, String> {
+    let scores: Vec<usize> = lines
         .iter()
-        .map(|line| parse_line(line))
+        .map(|line| parse_line(line, grammar))
         .filter(ParseResult::is_incomplete)
-        .map(|result| result.completion_score())
+        .map(|result| result.completion_score(grammar, scheme))
         .collect();
-    scores.sort_unstable();
-    scores[scores.len() / 2]
+    scheme.aggregate.apply(scores)
 }
 
-struct ParseResult {
-    stack: Stack<char>,
-    corrupt_token: Option<char>,
+/// How the completion scores of all incomplete lines combine into one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    /// The middle score once sorted; the upper middle for an even count.
+    #[default]
+    Median,
+    Sum,
+    Max,
 }
 
-impl ParseResult {
-    fn is_incomplete(&self) -> bool {
-        self.corrupt_token.is_none() && self.stack.size() > 0
+impl Aggregate {
+    fn apply(self, mut scores: Vec<usize>) -> Result<usize, String> {
+        if scores.is_empty() {
+            return Err("no incomplete lines to score".to_string());
+        }
+        Ok(match self {
+            Aggregate::Median => {
+                scores.sort_unstable();
+                scores[scores.len() / 2]
+            }
+            Aggregate::Sum => scores.iter().sum(),
+            Aggregate::Max => scores.into_iter().max().unwrap_or_default(),
+        })
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "median" => Ok(Aggregate::Median),
+            "sum" => Ok(Aggregate::Sum),
+            "max" => Ok(Aggregate::Max),
+            _ => Err(format!("unknown aggregate: {s}")),
+        }
+    }
+}
+
+/// Points for corrupt and missing tokens. Tokens without a value score 0.
+struct ScoringScheme {
+    syntax_error_values: HashMap<char, usize>,
+    completion_values: HashMap<char, usize>,
+    /// Folds each missing closer's value into the running line score.
+    fold: fn(usize, usize) -> usize,
+    aggregate: Aggregate,
+}
+
+impl Default for ScoringScheme {
+    /// The puzzle's scoring.
+    fn default() -> Self {
+        Self {
+            syntax_error_values: HashMap::from([(')', 3), (']', 57), ('}', 1197), ('>', 25137)]),
+            completion_values: HashMap::from([(')', 1), (']', 2), ('}', 3), ('>', 4)]),
+            fold: |total, value| total * 5 + value,
+            aggregate: Aggregate::default(),
+        }
+    }
+}
+
+impl ScoringScheme {
+    fn with_aggregate(self, aggregate: Aggregate) -> Self {
+        Self { aggregate, ..self }
+    }
+}
+
+/// What the checker does with a character that isn't part of any pair.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum OtherChars {
+    /// Skip it as if it weren't there.
+    Ignore,
+    /// Treat it as a corrupt token.
+    #[default]
+    Error,
+    /// Keep it as plain content of the enclosing chunk.
+    Literal,
+}
+
+impl FromStr for OtherChars {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(OtherChars::Ignore),
+            "error" => Ok(OtherChars::Error),
+            "literal" => Ok(OtherChars::Literal),
+            _ => Err(format!("unknown policy for other characters: {s}")),
+        }
+    }
+}
+
+/// Delimiter pairs written back to back, e.g. `()[]{}<>`.
+struct Pairs(Vec<(char, char)>);
+
+impl FromStr for Pairs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.chars().collect();
+        if !chars.len().is_multiple_of(2) {
+            return Err(format!("unpaired delimiter in {s:?}"));
+        }
+        Ok(Pairs(
+            chars.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+        ))
+    }
+}
+
+/// The bracket language a line is checked against.
+struct Grammar {
+    pairs: Vec<(char, char)>,
+    closers: HashMap<char, char>,
+    openers: HashMap<char, char>,
+    others: OtherChars,
+}
+
+impl Default for Grammar {
+    fn default() -> Self {
+        Self::new(
+            &[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')],
+            OtherChars::default(),
+        )
+        .expect("the puzzle grammar is valid")
     }
+}
 
-    fn syntax_error_score(&self) -> usize {
-        match self.corrupt_token {
-            Some(')') => 3,
-            Some(']') => 57,
-            Some('}') => 1197,
-            Some('>') => 25137,
-            _ => 0,
+impl Grammar {
+    fn new(pairs: &[(char, char)], others: OtherChars) -> Result<Self, String> {
+        if pairs.is_empty() {
+            return Err("a grammar needs at least one pair".to_string());
+        }
+        let mut closers = HashMap::new();
+        let mut openers = HashMap::new();
+        for &(open, close) in pairs {
+            if open == close {
+                return Err(format!("{open} can't both open and close a chunk"));
+            }
+            let reused = closers.contains_key(&open)
+                || closers.contains_key(&close)
+                || openers.contains_key(&open)
+                || openers.contains_key(&close);
+            if reused {
+                return Err(format!("{open}{close} reuses a delimiter"));
+            }
+            closers.insert(open, close);
+            openers.insert(close, open);
         }
+        Ok(Self {
+            pairs: pairs.to_vec(),
+            closers,
+            openers,
+            others,
+        })
     }
 
-    fn completion_score(&self) -> usize {
-        closing_tokens_for_result(self.stack.clone())
+    fn closer_for(&self, opener: char) -> Option<char> {
+        self.closers.get(&opener).copied()
+    }
+
+    fn opener_for(&self, closer: char) -> Option<char> {
+        self.openers.get(&closer).copied()
+    }
+
+    fn is_delimiter(&self, token: char) -> bool {
+        self.closers.contains_key(&token) || self.openers.contains_key(&token)
+    }
+
+    /// The cheapest way to turn `open` and `close` into a matching pair,
+    /// as the number of substitutions and the pair they become.
+    fn pairing(&self, open: char, close: char) -> (usize, char, char) {
+        self.pairs
             .iter()
-            .map(|&token| completion_score_for_token(token))
-            .fold(0, |total, token_score| total * 5 + token_score)
+            .map(|&(o, c)| (usize::from(o != open) + usize::from(c != close), o, c))
+            .min_by_key(|&(cost, _, _)| cost)
+            .expect("grammar has at least one pair")
     }
 }
 
-fn completion_score_for_token(token: char) -> usize {
-    match token {
-        ')' => 1,
-        ']' => 2,
-        '}' => 3,
-        '>' => 4,
-        _ => 0,
+/// Where and how a line first went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Corruption {
+    /// 0-based character column of the offending token.
+    column: usize,
+    found: char,
+    /// The closer for the innermost open chunk, if any chunk is open.
+    expected: Option<char>,
+}
+
+struct ParseResult {
+    stack: Stack<char>,
+    corruption: Option<Corruption>,
+    /// Top-level chunks, when the tree was asked for.
+    chunks: Option<Vec<Chunk>>,
+}
+
+impl ParseResult {
+    fn is_incomplete(&self) -> bool {
+        self.corruption.is_none() && self.stack.size() > 0
+    }
+
+    fn syntax_error_score(&self, scheme: &ScoringScheme) -> usize {
+        self.corruption
+            .and_then(|corruption| scheme.syntax_error_values.get(&corruption.found))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn completion_score(&self, grammar: &Grammar, scheme: &ScoringScheme) -> usize {
+        closing_tokens_for_result(self.stack.clone(), grammar)
+            .iter()
+            .map(|token| scheme.completion_values.get(token).copied().unwrap_or(0))
+            .fold(0, scheme.fold)
     }
 }
 
-fn closing_tokens_for_result(mut stack: Stack<char>) -> Vec<char> {
+fn closing_tokens_for_result(mut stack: Stack<char>, grammar: &Grammar) -> Vec<char> {
     let mut completion_tokens: Vec<char> = vec![];
     while let Some(token) = stack.pop() {
-        let closing_token = closing_tag_for(token);
-        completion_tokens.push(closing_token);
+        if let Some(closing_token) = grammar.closer_for(token) {
+            completion_tokens.push(closing_token);
+        }
     }
     completion_tokens
 }
 
-fn parse_line(line: &str) -> ParseResult {
-    let opening_tags = all_opening_tags();
+fn parse_line(line: &str, grammar: &Grammar) -> ParseResult {
+    parse(line, grammar, false)
+}
+
+/// Like [`parse_line`], but also records the chunk tree in
+/// [`ParseResult::chunks`].
+fn parse_line_tree(line: &str, grammar: &Grammar) -> ParseResult {
+    parse(line, grammar, true)
+}
+
+fn parse(line: &str, grammar: &Grammar, build_tree: bool) -> ParseResult {
     let mut stack: Stack<char> = Stack::default();
-    for token in line.chars() {
-        if opening_tags.contains(&token) {
+    let mut tree = build_tree.then(ChunkBuilder::default);
+    for (column, token) in line.chars().enumerate() {
+        if grammar.closer_for(token).is_some() {
             stack.push(token);
-        } else {
-            let expected_tag = opening_tag_for(token);
-            match stack.pop() {
-                Some(tag) if tag == expected_tag => (),
-                _ => {
-                    return ParseResult {
-                        stack,
-                        corrupt_token: Some(token),
-                    };
-                }
+            if let Some(tree) = tree.as_mut() {
+                tree.open(token, column);
+            }
+            continue;
+        }
+        let expected = stack.peek().and_then(|&open| grammar.closer_for(open));
+        let corrupt = match grammar.opener_for(token) {
+            Some(expected_tag) => stack.pop() != Some(expected_tag),
+            None => grammar.others == OtherChars::Error,
+        };
+        if corrupt {
+            return ParseResult {
+                stack,
+                corruption: Some(Corruption {
+                    column,
+                    found: token,
+                    expected,
+                }),
+                chunks: tree.map(|tree| tree.finish(column)),
+            };
+        }
+        if let Some(tree) = tree.as_mut() {
+            if grammar.opener_for(token).is_some() {
+                tree.close(token, column);
+            } else if grammar.others == OtherChars::Literal {
+                tree.literal(token);
             }
         }
     }
     ParseResult {
         stack,
-        corrupt_token: None,
+        corruption: None,
+        chunks: tree.map(|tree| tree.finish(line.chars().count())),
     }
 }
 
-fn all_opening_tags() -> HashSet<char> {
-    HashSet::from(['(', '[', '{', '<'])
+/// A delimited chunk of a line and the chunks nested directly inside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chunk {
+    open: char,
+    /// `None` when the line ends, or goes corrupt, before the chunk closes.
+    close: Option<char>,
+    /// Columns covered by the chunk, including its delimiters.
+    span: Range<usize>,
+    /// Literal characters directly inside the chunk, outside its children.
+    /// Only kept under [`OtherChars::Literal`].
+    content: String,
+    children: Vec<Chunk>,
 }
 
-fn opening_tag_for(token: char) -> char {
-    match token {
-        ')' => '(',
-        ']' => '[',
-        '}' => '{',
-        '>' => '<',
-        _ => panic!("not a valid token"),
+impl Chunk {
+    /// Levels of nesting in this chunk, counting itself.
+    fn depth(&self) -> usize {
+        1 + max_depth(&self.children)
+    }
+
+    fn pretty(&self, indent: usize, out: &mut String) {
+        let close = self.close.map_or_else(|| "…".to_string(), String::from);
+        let content = if self.content.is_empty() {
+            String::new()
+        } else {
+            format!(" {:?}", self.content)
+        };
+        let line = format!(
+            "{:indent$}{}{close} {}..{}{content}\n",
+            "",
+            self.open,
+            self.span.start,
+            self.span.end,
+            indent = indent * 2
+        );
+        out.push_str(&line);
+        for child in &self.children {
+            child.pretty(indent + 1, out);
+        }
     }
 }
 
-fn closing_tag_for(token: char) -> char {
-    match token {
-        '(' => ')',
-        '[' => ']',
-        '{' => '}',
-        '<' => '>',
-        _ => panic!("not a valid token"),
+/// The deepest nesting among `chunks`.
+fn max_depth(chunks: &[Chunk]) -> usize {
+    chunks.iter().map(Chunk::depth).max().unwrap_or(0)
+}
+
+/// Number of chunks at each depth: `histogram[d]` counts the chunks nested
+/// inside `d` others.
+fn depth_histogram(chunks: &[Chunk]) -> Vec<usize> {
+    let mut histogram = vec![];
+    let mut level: Vec<&Chunk> = chunks.iter().collect();
+    while !level.is_empty() {
+        histogram.push(level.len());
+        level = level.iter().flat_map(|chunk| &chunk.children).collect();
+    }
+    histogram
+}
+
+/// One line per chunk, indented by nesting, with `…` for chunks that never
+/// close and any literal content quoted after the span.
+fn render_tree(chunks: &[Chunk]) -> String {
+    let mut out = String::new();
+    for chunk in chunks {
+        chunk.pretty(0, &mut out);
+    }
+    out
+}
+
+/// Assembles chunks as the parser opens and closes them.
+#[derive(Default)]
+struct ChunkBuilder {
+    roots: Vec<Chunk>,
+    open: Vec<Chunk>,
+}
+
+impl ChunkBuilder {
+    fn open(&mut self, token: char, column: usize) {
+        self.open.push(Chunk {
+            open: token,
+            close: None,
+            span: column..column,
+            content: String::new(),
+            children: vec![],
+        });
+    }
+
+    /// Adds `token` to the content of the innermost open chunk. Literals
+    /// outside every chunk belong to none and are dropped.
+    fn literal(&mut self, token: char) {
+        if let Some(chunk) = self.open.last_mut() {
+            chunk.content.push(token);
+        }
     }
+
+    fn close(&mut self, token: char, column: usize) {
+        if let Some(mut chunk) = self.open.pop() {
+            chunk.close = Some(token);
+            chunk.span.end = column + 1;
+            self.attach(chunk);
+        }
+    }
+
+    fn attach(&mut self, chunk: Chunk) {
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(chunk),
+            None => self.roots.push(chunk),
+        }
+    }
+
+    /// Cuts off every chunk still open at `end`.
+    fn finish(mut self, end: usize) -> Vec<Chunk> {
+        while let Some(mut chunk) = self.open.pop() {
+            chunk.span.end = end;
+            self.attach(chunk);
+        }
+        self.roots
+    }
+}
+
+/// A single-character change to a line. Columns count characters of the
+/// original line; an insertion goes before the character at its column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Insert { column: usize, token: char },
+    Delete { column: usize, token: char },
+    Substitute { column: usize, from: char, to: char },
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Edit::Insert { column, token } => write!(f, "insert {token} at {column}"),
+            Edit::Delete { column, token } => write!(f, "delete {token} at {column}"),
+            Edit::Substitute { column, from, to } => {
+                write!(f, "replace {from} with {to} at {column}")
+            }
+        }
+    }
+}
+
+/// A balanced version of a line and the edits that produced it.
+struct Repair {
+    repaired: String,
+    edits: Vec<Edit>,
+}
+
+/// How the repair of a span starts, for rebuilding the cheapest repair.
+#[derive(Clone, Copy)]
+enum Choice {
+    /// Nothing left to repair.
+    Done,
+    /// The first character is content the grammar leaves alone.
+    Skip,
+    /// The first character can't be matched: complete it with an inserted
+    /// partner, or delete it if it isn't a delimiter.
+    Unmatched,
+    /// The first character pairs with the one at this index.
+    Pair(usize),
+}
+
+/// Finds the fewest insertions, deletions and substitutions that balance
+/// `line` under `grammar`.
+///
+/// `cost[i][j]` is the edit distance from `line[i..j]` to the nearest
+/// balanced string. The first character of a span is either left alone
+/// (other characters outside the `Error` policy), fixed on its own for one
+/// edit, or paired with a later character at the cost of making the two
+/// match, with the spans inside and after the pair repaired independently.
+fn repair(line: &str, grammar: &Grammar) -> Repair {
+    let tokens: Vec<char> = line.chars().collect();
+    let n = tokens.len();
+    let is_content =
+        |token: char| !grammar.is_delimiter(token) && grammar.others != OtherChars::Error;
+    let mut cost = vec![vec![0; n + 1]; n + 1];
+    let mut choice = vec![vec![Choice::Done; n + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in i + 1..=n {
+            if is_content(tokens[i]) {
+                cost[i][j] = cost[i + 1][j];
+                choice[i][j] = Choice::Skip;
+                continue;
+            }
+            cost[i][j] = 1 + cost[i + 1][j];
+            choice[i][j] = Choice::Unmatched;
+            for k in i + 1..j {
+                if is_content(tokens[k]) {
+                    continue;
+                }
+                let (pairing, _, _) = grammar.pairing(tokens[i], tokens[k]);
+                let total = pairing + cost[i + 1][k] + cost[k + 1][j];
+                if total < cost[i][j] {
+                    cost[i][j] = total;
+                    choice[i][j] = Choice::Pair(k);
+                }
+            }
+        }
+    }
+    let mut repair = Repair {
+        repaired: String::new(),
+        edits: vec![],
+    };
+    rebuild(&tokens, grammar, &choice, (0, n), &mut repair);
+    repair
+}
+
+fn rebuild(
+    tokens: &[char],
+    grammar: &Grammar,
+    choice: &[Vec<Choice>],
+    (i, j): (usize, usize),
+    repair: &mut Repair,
+) {
+    if i >= j {
+        return;
+    }
+    let token = tokens[i];
+    match choice[i][j] {
+        Choice::Done => {}
+        Choice::Skip => {
+            repair.repaired.push(token);
+            rebuild(tokens, grammar, choice, (i + 1, j), repair);
+        }
+        Choice::Unmatched => {
+            if let Some(closer) = grammar.closer_for(token) {
+                repair.repaired.push(token);
+                rebuild(tokens, grammar, choice, (i + 1, j), repair);
+                repair.repaired.push(closer);
+                repair.edits.push(Edit::Insert {
+                    column: j,
+                    token: closer,
+                });
+            } else if let Some(opener) = grammar.opener_for(token) {
+                repair.repaired.push(opener);
+                repair.repaired.push(token);
+                repair.edits.push(Edit::Insert {
+                    column: i,
+                    token: opener,
+                });
+                rebuild(tokens, grammar, choice, (i + 1, j), repair);
+            } else {
+                repair.edits.push(Edit::Delete { column: i, token });
+                rebuild(tokens, grammar, choice, (i + 1, j), repair);
+            }
+        }
+        Choice::Pair(k) => {
+            let (_, open, close) = grammar.pairing(token, tokens[k]);
+            repair.repaired.push(open);
+            if open != token {
+                repair.edits.push(Edit::Substitute {
+                    column: i,
+                    from: token,
+                    to: open,
+                });
+            }
+            rebuild(tokens, grammar, choice, (i + 1, k), repair);
+            repair.repaired.push(close);
+            if close != tokens[k] {
+                repair.edits.push(Edit::Substitute {
+                    column: k,
+                    from: tokens[k],
+                    to: close,
+                });
+            }
+            rebuild(tokens, grammar, choice, (k + 1, j), repair);
+        }
+    }
+}
+
+/// Prints `line` with a caret under the corrupt token and what was wrong.
+fn diagnostic(line: &str, corruption: &Corruption) -> String {
+    let message = match corruption.expected {
+        Some(expected) => format!("expected {expected}, found {}", corruption.found),
+        None => format!("unexpected {}", corruption.found),
+    };
+    format!(
+        "{line}\n{:width$}^ column {}: {message}",
+        "",
+        corruption.column,
+        width = corruption.column
+    )
 }
 
 #[cfg(test)]
@@ -147,13 +673,187 @@ mod day10_tests {
 
     #[test]
     fn test_part1() {
-        let result = part1(&input());
+        let result = part1(&input(), &Grammar::default(), &ScoringScheme::default());
         assert_eq!(26397, result);
     }
 
     #[test]
     fn test_part2() {
-        let result = part2(&input());
-        assert_eq!(288957, result);
+        let result = part2(&input(), &Grammar::default(), &ScoringScheme::default());
+        assert_eq!(Ok(288_957), result);
+    }
+
+    #[test]
+    fn test_scoring_schemes() {
+        let grammar = Grammar::default();
+        let sum = ScoringScheme::default().with_aggregate(Aggregate::Sum);
+        assert_eq!(
+            Ok(288_957 + 5_566 + 1_480_781 + 995_444 + 294),
+            part2(&input(), &grammar, &sum)
+        );
+        let max = ScoringScheme::default().with_aggregate(Aggregate::Max);
+        assert_eq!(Ok(1_480_781), part2(&input(), &grammar, &max));
+
+        let flat = ScoringScheme {
+            syntax_error_values: HashMap::from([(')', 1)]),
+            completion_values: HashMap::from([(')', 1), (']', 1), ('}', 1), ('>', 1)]),
+            fold: |total, value| total + value,
+            aggregate: Aggregate::Max,
+        };
+        assert_eq!(2, part1(&input(), &grammar, &flat));
+        assert_eq!(Ok(9), part2(&input(), &grammar, &flat));
+    }
+
+    #[test]
+    fn test_empty_scores() {
+        let lines = vec!["()".to_string(), "(]".to_string()];
+        let result = part2(&lines, &Grammar::default(), &ScoringScheme::default());
+        assert_eq!(Err("no incomplete lines to score".to_string()), result);
+        assert!("mode".parse::<Aggregate>().is_err());
+    }
+
+    #[test]
+    fn test_corruption_position() {
+        let result = parse_line("{([(<{}[<>[]}>{[]{[(<()>", &Grammar::default());
+        assert_eq!(
+            Some(Corruption {
+                column: 12,
+                found: '}',
+                expected: Some(']'),
+            }),
+            result.corruption
+        );
+        assert_eq!(
+            diagnostic("{([(<{}[<>[]}>{[]{[(<()>", &result.corruption.unwrap()),
+            "{([(<{}[<>[]}>{[]{[(<()>\n            ^ column 12: expected ], found }"
+        );
+    }
+
+    #[test]
+    fn test_other_characters() {
+        let pairs = [('(', ')'), ('|', '!')];
+        let line = "(a|b!)c";
+        let error = Grammar::new(&pairs, OtherChars::Error).unwrap();
+        let corruption = parse_line(line, &error).corruption.unwrap();
+        assert_eq!(
+            (1, 'a', Some(')')),
+            (corruption.column, corruption.found, corruption.expected)
+        );
+        for others in [OtherChars::Ignore, OtherChars::Literal] {
+            let grammar = Grammar::new(&pairs, others).unwrap();
+            let result = parse_line(line, &grammar);
+            assert!(result.corruption.is_none());
+            assert!(!result.is_incomplete());
+        }
+        let result = parse_line(")", &error);
+        assert_eq!(None, result.corruption.unwrap().expected);
+        assert_eq!("(|".parse::<Pairs>().unwrap().0, vec![('(', '|')]);
+        assert!(Grammar::new(&[('(', ')'), (')', '(')], OtherChars::Error).is_err());
+        assert!(Grammar::new(&[('|', '|')], OtherChars::Error).is_err());
+        assert!(Grammar::new(&"".parse::<Pairs>().unwrap().0, OtherChars::Error).is_err());
+    }
+
+    #[test]
+    fn test_chunk_tree() {
+        let grammar = Grammar::default();
+        let result = parse_line("[<>({}){}]()", &grammar);
+        assert!(result.chunks.is_none());
+        let chunks = parse_line_tree("[<>({}){}]()", &grammar).chunks.unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].span, 0..10);
+        assert_eq!(chunks[0].children.len(), 3);
+        assert_eq!(chunks[0].children[1].children[0].span, 4..6);
+        assert_eq!(chunks[1].span, 10..12);
+        assert_eq!(max_depth(&chunks), 3);
+        assert_eq!(depth_histogram(&chunks), vec![2, 3, 1]);
+        assert_eq!(
+            render_tree(&chunks),
+            "[] 0..10\n  <> 1..3\n  () 3..7\n    {} 4..6\n  {} 7..9\n() 10..12\n"
+        );
+    }
+
+    #[test]
+    fn test_literal_content() {
+        let pairs = [('(', ')'), ('[', ']')];
+        let line = "x(ab[c]d)(e";
+        let literal = Grammar::new(&pairs, OtherChars::Literal).unwrap();
+        let chunks = parse_line_tree(line, &literal).chunks.unwrap();
+        assert_eq!(chunks[0].content, "abd");
+        assert_eq!(chunks[0].children[0].content, "c");
+        assert_eq!(
+            render_tree(&chunks),
+            "() 1..9 \"abd\"\n  [] 4..7 \"c\"\n(… 9..11 \"e\"\n"
+        );
+        let ignore = Grammar::new(&pairs, OtherChars::Ignore).unwrap();
+        let chunks = parse_line_tree(line, &ignore).chunks.unwrap();
+        assert_eq!(render_tree(&chunks), "() 1..9\n  [] 4..7\n(… 9..11\n");
+    }
+
+    #[test]
+    fn test_unfinished_chunk_tree() {
+        let grammar = Grammar::default();
+        let chunks = parse_line_tree("(<[]", &grammar).chunks.unwrap();
+        assert_eq!(render_tree(&chunks), "(… 0..4\n  <… 1..4\n    [] 2..4\n");
+        let chunks = parse_line_tree("(<[]>]{}", &grammar).chunks.unwrap();
+        assert_eq!(render_tree(&chunks), "(… 0..5\n  <> 1..5\n    [] 2..4\n");
+        assert_eq!(depth_histogram(&[]), Vec::<usize>::new());
+    }
+
+    fn assert_balanced(line: &str, grammar: &Grammar) {
+        let result = parse_line(line, grammar);
+        assert!(
+            result.corruption.is_none() && !result.is_incomplete(),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn test_repair() {
+        let grammar = Grammar::default();
+        let repaired = repair("(]", &grammar);
+        assert_eq!(repaired.repaired, "()");
+        assert_eq!(
+            repaired.edits,
+            vec![Edit::Substitute {
+                column: 1,
+                from: ']',
+                to: ')'
+            }]
+        );
+
+        let repaired = repair("(()", &grammar);
+        assert_eq!(repaired.repaired, "(())");
+        assert_eq!(repaired.edits[0].to_string(), "insert ) at 3");
+
+        let repaired = repair("())", &grammar);
+        assert_eq!(repaired.repaired, "()()");
+        assert_eq!(repaired.edits[0].to_string(), "insert ( at 2");
+
+        let repaired = repair("[<>({}){}[([])<>]]", &grammar);
+        assert!(repaired.edits.is_empty());
+
+        for line in input() {
+            let repaired = repair(&line, &grammar);
+            assert_balanced(&repaired.repaired, &grammar);
+            assert!(!repaired.edits.is_empty());
+        }
+        assert_eq!(repair("[(])", &grammar).edits.len(), 2);
+    }
+
+    #[test]
+    fn test_repair_other_characters() {
+        let pairs = [('(', ')')];
+        let error = Grammar::new(&pairs, OtherChars::Error).unwrap();
+        let repaired = repair("(x)", &error);
+        assert_eq!(repaired.repaired, "()");
+        assert_eq!(repaired.edits[0].to_string(), "delete x at 1");
+        let repaired = repair("x)", &error);
+        assert_eq!(repaired.repaired, "()");
+        assert_eq!(repaired.edits[0].to_string(), "replace x with ( at 0");
+
+        let literal = Grammar::new(&pairs, OtherChars::Literal).unwrap();
+        let repaired = repair("(x", &literal);
+        assert_eq!(repaired.repaired, "(x)");
+        assert_eq!(repaired.edits.len(), 1);
     }
 }
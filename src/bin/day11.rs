@@ -1,141 +1,186 @@
 #![warn(clippy::all, clippy::pedantic)]
+use aoc2021::automaton::{Automaton, Edges, Grid, Rule};
+use aoc2021::cycle::{self, Cycle};
 use aoc2021::input_lines;
 use aoc2021::point::Point;
-use std::collections::HashSet;
-use std::ops::{Index, IndexMut};
+use std::env;
+use std::str::FromStr;
 
 fn main() {
-    let octopii = parse_input(input_lines());
-    let result = part1(octopii);
-    println!("Part 1: {}", result);
-    let result = part2(octopii);
-    println!("Part 2: {}", result);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let grid = parse_input(input_lines());
+    match args[..] {
+        ["replay", style, steps, ref options @ ..] => {
+            let mut octopii = Automaton::new(grid.with_edges(boundary_from(options)), Flash);
+            let style = style.parse().expect("invalid frame style");
+            let steps: usize = steps.parse().expect("steps must be a number");
+            print!("{}", render(octopii.grid(), &StepLog::default(), style));
+            for step in 1..=steps {
+                let log = StepLog {
+                    waves: octopii.step(),
+                };
+                println!(
+                    "\nStep {step}: {} flashes in {} waves",
+                    log.flash_count(),
+                    log.waves.len()
+                );
+                print!("{}", render(octopii.grid(), &log, style));
+            }
+        }
+        ref options => {
+            let octopii = Automaton::new(grid.with_edges(boundary_from(options)), Flash);
+            let result = part1(octopii.clone());
+            println!("Part 1: {result}");
+            match part2(octopii) {
+                Ok(result) => println!("Part 2: {result}"),
+                Err(Cycle { start, length }) => println!(
+                    "Part 2: never syncs; from step {start} the grid repeats every {length} steps"
+                ),
+            }
+        }
+    }
 }
 
-fn part1(mut octopii: Octopii) -> usize {
-    (0..100).map(|_| octopii.step()).sum()
+/// Reads the optional boundary argument: `open` or `torus`.
+fn boundary_from(options: &[&str]) -> Edges {
+    match options.first() {
+        None | Some(&"open") => Edges::Open,
+        Some(&("torus" | "toroidal")) => Edges::Wrapping,
+        Some(other) => panic!("unknown boundary: {other}"),
+    }
 }
 
-fn part2(mut octopii: Octopii) -> usize {
-    let mut step_number = 1;
-    while octopii.step() != 100 {
-        step_number += 1;
+type Octopii = Automaton<u8, Flash>;
+
+/// Every octopus gains a unit of energy each step. Once it passes 9 it
+/// flashes, giving a unit to each neighbor that hasn't flashed yet, and
+/// ends the step at 0.
+#[derive(Debug, Clone, Copy)]
+struct Flash;
+
+impl Rule<u8> for Flash {
+    fn next(&self, &energy: &u8, _: &[&u8]) -> u8 {
+        energy + 1
     }
 
-    step_number
-}
+    fn fires(&self, &energy: &u8) -> bool {
+        energy > 9
+    }
 
-#[derive(Clone, Copy)]
-struct Octopii {
-    map: [u8; 100],
-}
+    fn receive(&self, energy: &mut u8) {
+        *energy += 1;
+    }
 
-impl Default for Octopii {
-    fn default() -> Self {
-        Self { map: [0; 100] }
+    fn settle(&self, energy: &mut u8) {
+        *energy = 0;
     }
 }
 
-impl Octopii {
-    fn step(&mut self) -> usize {
-        let mut to_energize = all_points();
-        let mut flash_count = 0;
-        let mut flashed: HashSet<Point> = HashSet::default();
-
-        while let Some(point) = to_energize.pop() {
-            if !flashed.contains(&point) {
-                match self[point] {
-                    9 => {
-                        self[point] = 0;
-                        flash_count += 1;
-                        flashed.insert(point);
-                        to_energize.append(&mut adjacent_points(point));
-                    }
-                    _ => self[point] += 1,
-                }
-            }
-        }
+fn part1(mut octopii: Octopii) -> usize {
+    let mut flashes = 0;
+    octopii.run_until(|octopii, waves| {
+        flashes += waves.iter().map(Vec::len).sum::<usize>();
+        octopii.steps() == 100
+    });
+    flashes
+}
 
-        flash_count
-    }
+/// The first step on which every octopus flashes. Octopi that flashed end
+/// the step at 0, so that is the first all-zero grid after the start.
+fn part2(mut octopii: Octopii) -> Result<usize, Cycle> {
+    octopii.step();
+    cycle::search(
+        octopii.into_grid(),
+        |grid| {
+            let mut next = Automaton::new(grid.clone(), Flash);
+            next.step();
+            next.into_grid()
+        },
+        |grid| grid.cells().iter().all(|&energy| energy == 0),
+    )
+    .map(|steps| steps + 1)
+    .map_err(|cycle| Cycle {
+        start: cycle.start + 1,
+        ..cycle
+    })
 }
 
-fn all_points() -> Vec<Point> {
-    let mut points = Vec::with_capacity(100);
-    for y in 0..HEIGHT {
-        for x in 0..WIDTH {
-            points.push(Point { x, y });
-        }
-    }
-    points
+/// The octopi that flashed during one step, grouped by cascade wave.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct StepLog {
+    waves: Vec<Vec<Point>>,
 }
 
-fn adjacent_points(point: Point) -> Vec<Point> {
-    let mut result = vec![];
-    let steps: [i64; 3] = [-1, 0, 1];
-    for x in steps {
-        for y in steps {
-            if x != 0 || y != 0 {
-                let neighbor = point + Point { x, y };
-                if array_index_for_point(neighbor).is_some() {
-                    result.push(neighbor);
-                }
-            }
-        }
+impl StepLog {
+    fn flash_count(&self) -> usize {
+        self.waves.iter().map(Vec::len).sum()
     }
 
-    result
+    fn flashed(&self, point: Point) -> bool {
+        self.waves.iter().any(|wave| wave.contains(&point))
+    }
 }
 
-const WIDTH: i64 = 10;
-const HEIGHT: i64 = 10;
-fn array_index_for_point(point: Point) -> Option<usize> {
-    match point {
-        Point { x, y } if !(0..WIDTH).contains(&x) || !(0..HEIGHT).contains(&y) => None,
-        Point { x, y } => usize::try_from(y * WIDTH + x).ok(),
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameStyle {
+    Plain,
+    /// Flashing octopi highlighted with ANSI escapes.
+    Ansi,
 }
 
-impl Index<Point> for Octopii {
-    type Output = u8;
+impl FromStr for FrameStyle {
+    type Err = String;
 
-    fn index(&self, point: Point) -> &Self::Output {
-        match array_index_for_point(point) {
-            Some(i) => &self.map[i],
-            None => panic!("out of bounds"),
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(FrameStyle::Plain),
+            "ansi" => Ok(FrameStyle::Ansi),
+            _ => Err(format!("unknown frame style: {s}")),
         }
     }
 }
 
-impl IndexMut<Point> for Octopii {
-    fn index_mut(&mut self, point: Point) -> &mut Self::Output {
-        match array_index_for_point(point) {
-            Some(i) => &mut self.map[i],
-            None => panic!("out of bounds"),
+/// One line per row of energy levels. In the ANSI style the octopi that
+/// flashed during `log` are drawn bold and bright.
+fn render(grid: &Grid<u8>, log: &StepLog, style: FrameStyle) -> String {
+    let mut frame = String::new();
+    for point in grid.points() {
+        let energy = char::from(b'0' + grid[point]);
+        if style == FrameStyle::Ansi && log.flashed(point) {
+            frame.push_str("\x1b[1;93m");
+            frame.push(energy);
+            frame.push_str("\x1b[0m");
+        } else {
+            frame.push(energy);
+        }
+        if point.x == grid.width() - 1 {
+            frame.push('\n');
         }
     }
+    frame
 }
 
-fn parse_input<I>(lines: I) -> Octopii
+fn parse_input<I>(lines: I) -> Grid<u8>
 where
     I: Iterator<Item = String>,
 {
-    let mut octopii = Octopii::default();
-    for (y, line) in lines.enumerate() {
-        let line = line.trim();
-        for (x, c) in line.chars().enumerate() {
-            octopii[Point {
-                x: x as i64,
-                y: y as i64,
-            }] = u8::try_from(c.to_digit(10).expect("not a number")).unwrap();
-        }
-    }
-    octopii
+    let rows = lines
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.chars()
+                .map(|c| u8::try_from(c.to_digit(10).expect("not a number")).unwrap())
+                .collect()
+        })
+        .collect();
+    Grid::from_rows(rows).expect("rows must all be the same width")
 }
 
 #[cfg(test)]
 mod day11_tests {
     use super::*;
+    use aoc2021::automaton::Neighborhood;
 
     const INPUT: [&str; 10] = [
         "5483143223\n",
@@ -151,7 +196,23 @@ mod day11_tests {
     ];
 
     fn test_input() -> Octopii {
-        parse_input(INPUT.iter().map(|s| s.to_string()))
+        grid_of(&INPUT)
+    }
+
+    fn grid_of(lines: &[&str]) -> Octopii {
+        Automaton::new(parse_input(lines.iter().map(ToString::to_string)), Flash)
+    }
+
+    fn torus_of(lines: &[&str]) -> Octopii {
+        let grid = parse_input(lines.iter().map(ToString::to_string));
+        Automaton::new(grid.with_edges(Edges::Wrapping), Flash)
+    }
+
+    fn flashes(octopii: &mut Octopii) -> usize {
+        StepLog {
+            waves: octopii.step(),
+        }
+        .flash_count()
     }
 
     #[test]
@@ -161,6 +222,88 @@ mod day11_tests {
 
     #[test]
     fn test_part2() {
-        assert_eq!(195, part2(test_input()));
+        assert_eq!(Ok(195), part2(test_input()));
+    }
+
+    #[test]
+    fn test_never_syncs() {
+        let octopii = grid_of(&["0", "2"]);
+        assert_eq!(
+            Err(Cycle {
+                start: 1,
+                length: 9
+            }),
+            part2(octopii)
+        );
+        assert_eq!(Ok(10), part2(grid_of(&["0"])));
+    }
+
+    #[test]
+    fn test_rectangular_grid() {
+        let mut octopii = grid_of(&["11111", "19991", "19191", "19991", "11111"]);
+        assert_eq!((octopii.grid().width(), octopii.grid().height()), (5, 5));
+        assert_eq!(9, flashes(&mut octopii));
+        assert_eq!(octopii.grid().cells()[..5], [3, 4, 5, 4, 3]);
+
+        let mut wide = grid_of(&["9000", "0000"]);
+        assert_eq!((wide.grid().width(), wide.grid().height()), (4, 2));
+        assert_eq!(1, flashes(&mut wide));
+        assert_eq!(wide.grid().cells(), &[0, 2, 1, 1, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn test_cascade_waves() {
+        let mut octopii = grid_of(&["11111", "19991", "19191", "19991", "11111"]);
+        octopii.step();
+        let log = StepLog {
+            waves: octopii.step(),
+        };
+        assert_eq!(log, StepLog::default());
+        assert_eq!(
+            render(octopii.grid(), &log, FrameStyle::Plain),
+            "45654\n51115\n61116\n51115\n45654\n"
+        );
+
+        let mut chain = grid_of(&["9899"]);
+        let log = StepLog {
+            waves: chain.step(),
+        };
+        assert_eq!(log.flash_count(), 4);
+        assert_eq!(
+            log.waves,
+            vec![
+                vec![
+                    Point { x: 0, y: 0 },
+                    Point { x: 2, y: 0 },
+                    Point { x: 3, y: 0 }
+                ],
+                vec![Point { x: 1, y: 0 }],
+            ]
+        );
+        assert_eq!(
+            render(grid_of(&["9899"]).grid(), &log, FrameStyle::Ansi),
+            "\x1b[1;93m9\x1b[0m\x1b[1;93m8\x1b[0m\x1b[1;93m9\x1b[0m\x1b[1;93m9\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_toroidal_boundary() {
+        let lines = ["9000", "0000", "0000"];
+        let mut open = grid_of(&lines);
+        open.step();
+        assert_eq!(open.grid()[Point { x: 3, y: 2 }], 1);
+        let mut torus = torus_of(&lines);
+        torus.step();
+        assert_eq!(torus.grid()[Point { x: 3, y: 2 }], 2);
+        let corner = Point { x: 0, y: 0 };
+        assert_eq!(torus.grid().neighbors(corner, Neighborhood::Moore).len(), 8);
+
+        let mut tiny = torus_of(&["90"]);
+        assert_eq!(
+            tiny.grid().neighbors(corner, Neighborhood::Moore),
+            vec![Point { x: 1, y: 0 }]
+        );
+        assert_eq!(1, flashes(&mut tiny));
+        assert_eq!(tiny.grid().cells(), &[0, 2]);
     }
 }
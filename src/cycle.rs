@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Where a deterministic sequence of states starts repeating itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    /// Steps taken before the first state that is part of the loop.
+    pub start: usize,
+    /// Steps it takes to get back to that state.
+    pub length: usize,
+}
+
+/// Steps `state` forward until `found` accepts it, returning how many steps
+/// that took (0 if `initial` is already accepted).
+///
+/// Every state seen is remembered by its hash, so the search also stops as
+/// soon as a state comes round again: `step` is deterministic, so from
+/// there on nothing new can happen and the loop is returned instead.
+///
+/// # Errors
+///
+/// Returns the [`Cycle`] the states fall into when no state is ever found.
+pub fn search<S, F, P>(initial: S, mut step: F, mut found: P) -> Result<usize, Cycle>
+where
+    S: Clone + Hash + Eq,
+    F: FnMut(&S) -> S,
+    P: FnMut(&S) -> bool,
+{
+    let mut seen: HashMap<S, usize> = HashMap::new();
+    let mut state = initial;
+    for steps in 0.. {
+        if found(&state) {
+            return Ok(steps);
+        }
+        let next = step(&state);
+        if let Some(start) = seen.insert(state, steps) {
+            return Err(Cycle {
+                start,
+                length: steps - start,
+            });
+        }
+        state = next;
+    }
+    unreachable!("ran out of steps")
+}
+
+/// The cycle reached by repeatedly applying `step` to `initial`. Only
+/// returns if the states do repeat, which is guaranteed when there are
+/// finitely many of them.
+pub fn find_cycle<S, F>(initial: S, step: F) -> Cycle
+where
+    S: Clone + Hash + Eq,
+    F: FnMut(&S) -> S,
+{
+    match search(initial, step, |_| false) {
+        Ok(_) => unreachable!("nothing is ever found"),
+        Err(cycle) => cycle,
+    }
+}
+
+#[cfg(test)]
+mod cycle_tests {
+    use super::*;
+
+    #[test]
+    fn test_find_cycle() {
+        // 12 drops to 3, then 3 → 9 → 7 → 1 → 3 under tripling mod 10.
+        let cycle = find_cycle(12_u64, |&n| if n > 10 { n - 9 } else { n * 3 % 10 });
+        assert_eq!(
+            cycle,
+            Cycle {
+                start: 1,
+                length: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        assert_eq!(
+            find_cycle(0_u8, |&n| n),
+            Cycle {
+                start: 0,
+                length: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_search_found() {
+        assert_eq!(Ok(0), search(4, |&n: &i32| n + 1, |&n| n == 4));
+        assert_eq!(Ok(6), search(1_u32, |&n| n * 2 % 100, |&n| n == 64));
+    }
+
+    #[test]
+    fn test_search_cycle() {
+        let result = search(1_u32, |&n| n * 2 % 100, |&n| n == 3);
+        assert_eq!(
+            Err(Cycle {
+                start: 2,
+                length: 20
+            }),
+            result
+        );
+    }
+}
@@ -1,7 +1,10 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![feature(stdin_forwarders)]
 
+pub mod automaton;
+pub mod cycle;
 pub mod point;
+pub mod rng;
 pub mod stack;
 
 use std::io;
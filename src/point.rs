@@ -1,5 +1,5 @@
 use std::num::ParseIntError;
-use std::ops::{Add, AddAssign};
+use std::ops::{Add, AddAssign, Sub};
 use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -38,3 +38,57 @@ impl AddAssign for Point {
         };
     }
 }
+
+impl Sub for Point {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            x: self.x - other.x,
+            y: self.y - other.y,
+        }
+    }
+}
+
+impl Point {
+    /// The smallest step with integer components in the same direction,
+    /// i.e. both components divided by their greatest common divisor.
+    #[must_use]
+    pub fn reduced(self) -> Self {
+        match gcd(self.x.abs(), self.y.abs()) {
+            0 => self,
+            divisor => Self {
+                x: self.x / divisor,
+                y: self.y / divisor,
+            },
+        }
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod point_tests {
+    use super::*;
+
+    #[test]
+    fn test_sub() {
+        let result = Point { x: 4, y: 2 } - Point { x: 1, y: 5 };
+        assert_eq!(Point { x: 3, y: -3 }, result);
+    }
+
+    #[test]
+    fn test_reduced() {
+        assert_eq!(Point { x: 2, y: -1 }, Point { x: 4, y: -2 }.reduced());
+        assert_eq!(Point { x: 0, y: 1 }, Point { x: 0, y: 7 }.reduced());
+        assert_eq!(Point { x: -1, y: -1 }, Point { x: -3, y: -3 }.reduced());
+        assert_eq!(Point { x: 3, y: 5 }, Point { x: 3, y: 5 }.reduced());
+        assert_eq!(Point { x: 0, y: 0 }, Point { x: 0, y: 0 }.reduced());
+    }
+}